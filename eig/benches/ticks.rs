@@ -0,0 +1,42 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+//! Ticks per second against interval count, on a lattice of pulls braced by pushes.
+//!
+//!     cargo bench --bench ticks
+//!     cargo bench --bench ticks --features parallel
+
+use std::time::{Duration, Instant};
+
+use eig::constants::{Real, Stage, WorldFeature};
+use eig::fabric::Fabric;
+use eig::world::World;
+
+const MEASURE: Duration = Duration::from_millis(500);
+
+fn main() {
+    let world = World::new();
+    let parallel = if cfg!(feature = "parallel") { "parallel" } else { "serial" };
+    println!("{:>10} {:>10} {:>14}  ({})", "joints", "intervals", "ticks/second", parallel);
+    for side in [5, 8, 11, 14, 18, 22] {
+        let mut fabric = Fabric::lattice(side);
+        fabric.request_stage(Stage::Shaping, &world);
+        fabric.request_stage(Stage::Pretenst, &world);
+        let start = Instant::now();
+        let mut frames = 0;
+        while start.elapsed() < MEASURE {
+            fabric.iterate(&world);
+            frames += 1;
+        }
+        let ticks_per_frame: Real = world.get_float_value(WorldFeature::IterationsPerFrame);
+        let ticks = frames as f64 * ticks_per_frame as f64;
+        println!(
+            "{:>10} {:>10} {:>14.0}",
+            fabric.get_joint_count(),
+            fabric.get_interval_count(),
+            ticks / start.elapsed().as_secs_f64(),
+        );
+    }
+}
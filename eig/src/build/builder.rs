@@ -0,0 +1,487 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use log::debug;
+
+use crate::build::tensegrity::{FaceAction, Role, Tensegrity};
+use crate::build::twist::{create_base, Spin, TwistFace};
+use crate::constants::{Real, Stage};
+use crate::fabric::Fabric;
+use crate::tenscript::{Chirality, FabricPlan, FaceName, PretenseStep, SeedType, ShapeOperation, TenscriptNode};
+use crate::world::{check_features, FeatureError, World};
+
+#[derive(Debug, Clone)]
+pub enum BuildError {
+    FaceNotOnTwist { face: FaceName },
+    UnknownMark { mark_name: String },
+    NothingGrown { face: FaceName },
+    FaceGrownTwice { face: FaceName },
+    Feature(FeatureError),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::FaceNotOnTwist { face } => write!(f, "face {face} is not on the twist, which may need to be omni"),
+            BuildError::UnknownMark { mark_name } => write!(f, "mark :{mark_name} is never placed on a face"),
+            BuildError::NothingGrown { face } => write!(f, "the grow on face {face} has no twists"),
+            BuildError::FaceGrownTwice { face } => write!(f, "face {face} is grown on twice"),
+            BuildError::Feature(error) => write!(f, "pretense {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+#[derive(Debug, Clone)]
+struct Bud {
+    twist: usize,
+    face: FaceName,
+    step: usize,
+    node: TenscriptNode,
+}
+
+/// Interprets a `FabricPlan`, growing it into a `Fabric` and taking it through the stages.
+pub struct Builder {
+    plan: FabricPlan,
+    tensegrity: Tensegrity,
+    buds: Option<Vec<Bud>>,
+    shaped: bool,
+    pretense_step: usize,
+    wait_until: Option<u32>,
+}
+
+impl Builder {
+    pub fn new(plan: FabricPlan, world: &World) -> Result<Builder, BuildError> {
+        validate(&plan)?;
+        Ok(Builder {
+            plan,
+            tensegrity: Tensegrity::new(world.interval_countdown),
+            buds: None,
+            shaped: false,
+            pretense_step: 0,
+            wait_until: None,
+        })
+    }
+
+    pub fn tensegrity(&self) -> &Tensegrity {
+        &self.tensegrity
+    }
+
+    /// Iterate the fabric, and whenever it is no longer busy take the next step of the plan.
+    /// The pretense phase's features are applied to the world as the fabric goes slack.
+    /// Returns false when the whole plan has been carried out.
+    pub fn iterate(&mut self, fabric: &mut Fabric, world: &mut World) -> bool {
+        if self.buds.is_none() {
+            self.plant_seed(fabric);
+            return true;
+        }
+        let busy = fabric.iterate(world);
+        for index in fabric.take_removed_intervals() {
+            self.tensegrity.forget_interval(index);
+        }
+        if busy {
+            return true;
+        }
+        match fabric.get_stage() {
+            Stage::Growing => {
+                if self.buds.as_ref().is_some_and(|buds| !buds.is_empty()) {
+                    self.grow(fabric);
+                } else if !self.shaped {
+                    self.shape(fabric);
+                    self.shaped = true;
+                } else if !self.tensegrity.check_connectors(fabric) {
+                    request_stage(fabric, Stage::Shaping, world);
+                    if let Some(vulcanize_type) = self.plan.build_phase.vulcanize {
+                        self.tensegrity.vulcanize(fabric, vulcanize_type);
+                    }
+                }
+                true
+            }
+            Stage::Shaping => {
+                let Some(pretense_phase) = &self.plan.pretense_phase else {
+                    return false;
+                };
+                world
+                    .apply_features(&pretense_phase.features)
+                    .expect("features checked when the builder was created");
+                self.tensegrity.remove_distancers(fabric, Role::ShapingDistancer);
+                request_stage(fabric, Stage::Slack, world);
+                true
+            }
+            Stage::Slack => {
+                request_stage(fabric, Stage::Pretensing, world);
+                true
+            }
+            Stage::Pretensing => {
+                self.tensegrity.remove_distancers(fabric, Role::PretenstDistancer);
+                request_stage(fabric, Stage::Pretenst, world);
+                true
+            }
+            Stage::Pretenst => self.pretense(fabric),
+        }
+    }
+
+    fn plant_seed(&mut self, fabric: &mut Fabric) {
+        fabric.clear();
+        let build_phase = &self.plan.build_phase;
+        let spin = Spin::from_seed(seed_type(build_phase.seed, build_phase.growth.as_ref()));
+        let scale = build_phase.scale.map_or(1.0, |percent| percent as Real / 100.0);
+        debug!("planting a {spin:?} seed");
+        let seed = self.tensegrity.create_twist(fabric, spin, scale, create_base(nalgebra::zero()));
+        let buds = match &build_phase.growth {
+            Some(node) => branch_buds(seed, node),
+            None => Vec::new(),
+        };
+        self.buds = Some(buds);
+    }
+
+    fn grow(&mut self, fabric: &mut Fabric) {
+        let buds = self.buds.take().unwrap_or_default();
+        let mut next_buds = Vec::new();
+        for Bud { twist, face, step, node } in buds {
+            let TenscriptNode::Grow { forward, scale, marks, branch, .. } = &node else {
+                continue;
+            };
+            if let Some(&chirality) = forward.get(step) {
+                let base_face = self.face_of(twist, face);
+                let TwistFace { spin: base_spin, scale: base_scale, .. } = self.tensegrity.faces[base_face];
+                let to_omni = step + 1 == forward.len() && needs_omni(&node);
+                let spin = match chirality {
+                    Chirality::Alternate => base_spin.change(true, to_omni),
+                    Chirality::Left => Spin::Left.change(false, to_omni),
+                    Chirality::Right => Spin::Right.change(false, to_omni),
+                };
+                let scale = base_scale * scale.map_or(1.0, |factor| factor as Real);
+                let twist = self.tensegrity.create_twist_on(fabric, base_face, spin, scale);
+                next_buds.push(Bud { twist, face: FaceName::Aplus, step: step + 1, node });
+            } else {
+                for mark in marks {
+                    let marked_face = self.face_of(twist, mark.face);
+                    self.tensegrity.faces[marked_face].marks.push(mark.name.clone());
+                }
+                if let Some(branch) = branch {
+                    next_buds.extend(branch_buds(twist, branch));
+                }
+            }
+        }
+        self.buds = Some(next_buds);
+    }
+
+    fn shape(&mut self, fabric: &mut Fabric) {
+        for operation in &self.plan.shape_phase.operations {
+            let (mark_name, action) = match operation {
+                ShapeOperation::PullTogether { mark_name, percent: None } |
+                ShapeOperation::Join { mark_name } =>
+                    (mark_name, FaceAction::Join),
+                ShapeOperation::PullTogether { mark_name, percent: Some(percent) } =>
+                    (mark_name, FaceAction::ShapingDistance(*percent as Real / 100.0)),
+                ShapeOperation::Distance { mark_name, percent } =>
+                    (mark_name, FaceAction::PretenstDistance(*percent as Real / 100.0)),
+            };
+            let faces = self.tensegrity.marked_faces(mark_name);
+            self.tensegrity.create_radial_pulls(fabric, &faces, action);
+        }
+    }
+
+    fn pretense(&mut self, fabric: &mut Fabric) -> bool {
+        let Some(pretense_phase) = &self.plan.pretense_phase else {
+            return false;
+        };
+        let Some(step) = pretense_phase.steps.get(self.pretense_step) else {
+            return false;
+        };
+        if self.wait_until.is_none() {
+            debug!("pretense {step:?} at age {}", fabric.age);
+        }
+        match step {
+            PretenseStep::Wait { iterations } => {
+                let wait_until = *self.wait_until.get_or_insert(fabric.age + iterations);
+                if fabric.age < wait_until {
+                    return true;
+                }
+                self.wait_until = None;
+            }
+            PretenseStep::ContractConflicts => {
+                self.tensegrity.create_conflict_pulls(fabric);
+            }
+            PretenseStep::Orient { mark_name } => {
+                let faces = self.tensegrity.marked_faces(mark_name);
+                self.tensegrity.orient(fabric, &faces);
+            }
+        }
+        self.pretense_step += 1;
+        true
+    }
+
+    fn face_of(&self, twist: usize, face: FaceName) -> usize {
+        self.tensegrity.twists[twist]
+            .face(face)
+            .expect("plan validated against twist faces")
+    }
+}
+
+fn request_stage(fabric: &mut Fabric, stage: Stage, world: &World) {
+    debug!("{:?} to {stage:?} at age {}", fabric.get_stage(), fabric.age);
+    fabric.request_stage(stage, world);
+}
+
+/// A seed only has the lateral faces when it is omni, so a seed with its chirality given is
+/// promoted to the omni seed starting with that chirality when something grows sideways.
+fn seed_type(seed: Option<SeedType>, growth: Option<&TenscriptNode>) -> SeedType {
+    let lateral = match growth {
+        Some(TenscriptNode::Branch { subtrees }) => subtrees.iter().any(|subtree| !is_axial(grow_face(subtree))),
+        Some(node) => !is_axial(grow_face(node)),
+        None => false,
+    };
+    match (seed.unwrap_or(SeedType::Left), lateral) {
+        (SeedType::Left, true) => SeedType::LeftRight,
+        (SeedType::Right, true) => SeedType::RightLeft,
+        (seed, _) => seed,
+    }
+}
+
+fn branch_buds(twist: usize, node: &TenscriptNode) -> Vec<Bud> {
+    match node {
+        TenscriptNode::Grow { face, .. } => vec![Bud {
+            twist,
+            face: *face,
+            step: 0,
+            node: node.clone(),
+        }],
+        TenscriptNode::Branch { subtrees } => subtrees
+            .iter()
+            .flat_map(|subtree| branch_buds(twist, subtree))
+            .collect(),
+    }
+}
+
+fn grow_face(node: &TenscriptNode) -> FaceName {
+    match node {
+        TenscriptNode::Grow { face, .. } => *face,
+        TenscriptNode::Branch { .. } => FaceName::Aplus,
+    }
+}
+
+fn is_axial(face: FaceName) -> bool {
+    matches!(face, FaceName::Aplus | FaceName::Aminus)
+}
+
+/// The last twist of a grow needs all eight faces if anything goes on beyond its ends.
+fn needs_omni(node: &TenscriptNode) -> bool {
+    let TenscriptNode::Grow { marks, branch, .. } = node else {
+        return false;
+    };
+    let branch_faces = match branch.as_deref() {
+        Some(TenscriptNode::Branch { subtrees }) => subtrees.iter().map(grow_face).collect(),
+        Some(grow) => vec![grow_face(grow)],
+        None => vec![],
+    };
+    marks.iter().map(|mark| mark.face).chain(branch_faces).any(|face| !is_axial(face))
+}
+
+fn validate(plan: &FabricPlan) -> Result<(), BuildError> {
+    let mut marks = HashSet::new();
+    if let Some(growth) = &plan.build_phase.growth {
+        let seed = seed_type(plan.build_phase.seed, Some(growth));
+        validate_node(growth, Spin::from_seed(seed).is_omni(), &mut marks)?;
+    }
+    let shape_marks = plan.shape_phase.operations.iter().map(|operation| match operation {
+        ShapeOperation::PullTogether { mark_name, .. } |
+        ShapeOperation::Distance { mark_name, .. } |
+        ShapeOperation::Join { mark_name } => mark_name,
+    });
+    let orient_marks = plan.pretense_phase.iter().flat_map(|phase| &phase.steps).filter_map(|step| match step {
+        PretenseStep::Orient { mark_name } => Some(mark_name),
+        _ => None,
+    });
+    if let Some(mark_name) = shape_marks.chain(orient_marks).find(|&mark_name| !marks.contains(mark_name)) {
+        return Err(BuildError::UnknownMark { mark_name: mark_name.clone() });
+    }
+    if let Some(pretense_phase) = &plan.pretense_phase {
+        check_features(&pretense_phase.features).map_err(BuildError::Feature)?;
+    }
+    Ok(())
+}
+
+fn validate_node(node: &TenscriptNode, parent_omni: bool, marks: &mut HashSet<String>) -> Result<(), BuildError> {
+    let check = |face: FaceName, omni: bool| match face {
+        FaceName::Seed => Err(BuildError::FaceNotOnTwist { face }),
+        _ if omni || is_axial(face) => Ok(()),
+        _ => Err(BuildError::FaceNotOnTwist { face }),
+    };
+    match node {
+        TenscriptNode::Grow { face, forward, marks: node_marks, branch, .. } => {
+            check(*face, parent_omni)?;
+            if forward.is_empty() {
+                return Err(BuildError::NothingGrown { face: *face });
+            }
+            let omni = needs_omni(node);
+            for mark in node_marks {
+                check(mark.face, omni)?;
+                marks.insert(mark.name.clone());
+            }
+            if let Some(branch) = branch {
+                validate_node(branch, omni, marks)?;
+            }
+        }
+        TenscriptNode::Branch { subtrees } => {
+            let mut faces = HashSet::new();
+            if let Some(face) = subtrees.iter().map(grow_face).find(|&face| !faces.insert(face)) {
+                return Err(BuildError::FaceGrownTwice { face });
+            }
+            for subtree in subtrees {
+                validate_node(subtree, parent_omni, marks)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build::tensegrity::Role;
+    use crate::build::{BuildError, Builder};
+    use crate::constants::{FailureMode, Stage, WorldFeature};
+    use crate::fabric::Fabric;
+    use crate::material::Material;
+    use crate::tenscript::{parse, FabricPlan, FaceName, TenscriptNode};
+    use crate::world::World;
+
+    fn example(name: &str) -> FabricPlan {
+        let source = include_str!("../tenscript/examples.ss");
+        let start = source.find(&format!("(name \"{name}\")")).expect("example");
+        let start = source[..start].rfind("(fabric").unwrap();
+        let end = source[start + 1..].find("\n(fabric").map_or(source.len(), |end| start + 1 + end);
+        parse(&source[start..end]).unwrap()
+    }
+
+    fn examples() -> Vec<FabricPlan> {
+        crate::tenscript::parse_all(include_str!("../tenscript/examples.ss")).unwrap()
+    }
+
+    fn build(plan: FabricPlan, max_frames: usize) -> (Fabric, Builder) {
+        let mut world = World::from_plan(&plan).unwrap();
+        let mut fabric = Fabric::new(1000);
+        let mut builder = Builder::new(plan, &world).unwrap();
+        for _ in 0..max_frames {
+            if !builder.iterate(&mut fabric, &mut world) {
+                return (fabric, builder);
+            }
+        }
+        panic!("not built within {} frames", max_frames);
+    }
+
+    fn counts(fabric: &Fabric) -> (u16, u16) {
+        (fabric.get_joint_count(), fabric.get_interval_count())
+    }
+
+    fn has_role(builder: &Builder, role: Role) -> bool {
+        builder.tensegrity().specs.iter().any(|spec| spec.role == role)
+    }
+
+    #[test]
+    fn plants_a_seed() {
+        let (fabric, builder) = build(example("Single Seed"), 1000);
+        assert_eq!(counts(&fabric), (8, 12));
+        assert_eq!(builder.tensegrity().specs.len(), 12);
+        assert!(fabric.joints.iter().all(|joint| joint.location.coords.iter().all(|c| c.is_finite())));
+    }
+
+    #[test]
+    fn grows_a_twist() {
+        let (fabric, _) = build(parse("(fabric (build (seed :left) (grow A+ 1)))").unwrap(), 1000);
+        assert_eq!(counts(&fabric), (14, 24));
+    }
+
+    #[test]
+    fn promotes_a_seed_with_lateral_branches() {
+        let plan = parse("(fabric (build (seed :left) (branch (grow B- 1) (grow D- 1))))").unwrap();
+        let (fabric, _) = build(plan, 1000);
+        assert_eq!(counts(&fabric), (32, 54));
+        let plan = example("Halo by Crane");
+        let mut world = World::from_plan(&plan).unwrap();
+        let mut fabric = Fabric::new(1000);
+        let mut builder = Builder::new(plan, &world).unwrap();
+        builder.iterate(&mut fabric, &mut world);
+        assert_eq!(counts(&fabric), (20, 30));
+    }
+
+    #[test]
+    fn removes_distancers() {
+        let plan = "(fabric (build (seed :left) (branch (grow A+ 2 (mark A+ :end)) (grow A- 2 (mark A+ :end)))) (shape ({} :end 50%)) (pretense))";
+        let (fabric, builder) = build(parse(&plan.replace("{}", "pull-together")).unwrap(), 20_000);
+        assert!(!has_role(&builder, Role::ShapingDistancer));
+        let shaped = counts(&fabric);
+        let (fabric, builder) = build(parse(&plan.replace("{}", "distance")).unwrap(), 20_000);
+        assert!(!has_role(&builder, Role::PretenstDistancer));
+        assert_eq!(counts(&fabric), shaped);
+        assert_eq!(builder.tensegrity().specs.len(), fabric.get_interval_count() as usize);
+    }
+
+    #[test]
+    fn builds_every_example() {
+        for plan in examples() {
+            let name = plan.name.clone().unwrap_or_default();
+            let mut world = World::from_plan(&plan).unwrap();
+            world.set_float_value(WorldFeature::IterationsPerFrame, 5.0);
+            let mut fabric = Fabric::new(1000);
+            let mut builder = Builder::new(plan, &world).unwrap();
+            for _ in 0..1000 {
+                if !builder.iterate(&mut fabric, &mut world) {
+                    break;
+                }
+            }
+            let finite = fabric.joints.iter().all(|joint| joint.location.coords.iter().all(|c| c.is_finite()));
+            assert!(finite, "{} went astray", name);
+        }
+    }
+
+    #[test]
+    fn forgets_intervals_that_fail_during_pretense() {
+        let plan = parse("(fabric (build (seed :left) (grow A+ 2)) (pretense (wait 200) (contract-conflicts) (wait 200)))").unwrap();
+        let mut world = World::from_plan(&plan).unwrap();
+        world.set_failure_mode(FailureMode::Remove);
+        let mut fabric = Fabric::new(100);
+        let mut builder = Builder::new(plan, &world).unwrap();
+        while fabric.get_stage() != Stage::Slack {
+            assert!(builder.iterate(&mut fabric, &mut world));
+        }
+        fabric.set_materials(Material::SteelRod, Material::DyneemaLine);
+        fabric.set_metres_per_unit(2.0);
+        let intervals = fabric.get_interval_count();
+        while builder.iterate(&mut fabric, &mut world) {
+            assert_eq!(builder.tensegrity().specs.len(), fabric.get_interval_count() as usize);
+        }
+        assert!(!fabric.failures().is_empty() && fabric.get_interval_count() < intervals);
+        assert!(fabric.take_removed_intervals().is_empty());
+    }
+
+    #[test]
+    fn rejects_grows_that_collide() {
+        let mut plan = parse("(fabric (build (seed :left-right) (branch (grow B+ 2) (grow C+ 2))))").unwrap();
+        let Some(TenscriptNode::Branch { subtrees }) = &mut plan.build_phase.growth else {
+            panic!("expected a branch");
+        };
+        subtrees[1] = subtrees[0].clone();
+        assert!(matches!(Builder::new(plan, &World::new()), Err(BuildError::FaceGrownTwice { face: FaceName::Bplus })));
+        let mut plan = parse("(fabric (build (grow A+ 2)))").unwrap();
+        let Some(TenscriptNode::Grow { forward, .. }) = &mut plan.build_phase.growth else {
+            panic!("expected a grow");
+        };
+        forward.clear();
+        assert!(matches!(Builder::new(plan, &World::new()), Err(BuildError::NothingGrown { face: FaceName::Aplus })));
+    }
+
+    #[test]
+    fn grows_one_twist_without_a_count() {
+        let plan = parse("(fabric (build (seed :left-right) (branch (grow B+ (mark A+ :end)) (grow C+ 1 (mark A+ :end)))))").unwrap();
+        let (fabric, builder) = build(plan, 1000);
+        assert_eq!(counts(&fabric), (32, 54));
+        let ends = builder.tensegrity().marked_faces("end");
+        assert_eq!(ends.len(), 2);
+        let tensegrity = builder.tensegrity();
+        let heights: Vec<_> = ends.iter().map(|&face| tensegrity.faces[face].location(&fabric)).collect();
+        assert!((heights[0] - heights[1]).magnitude() > 1.0, "both grows ended up in the same place");
+    }
+}
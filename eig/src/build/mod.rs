@@ -0,0 +1,8 @@
+mod builder;
+mod tensegrity;
+mod twist;
+mod vulcanize;
+
+pub use builder::{BuildError, Builder};
+pub use tensegrity::{Role, Tensegrity};
+pub use twist::{Spin, Twist, TwistFace};
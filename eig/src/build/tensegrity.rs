@@ -0,0 +1,443 @@
+use nalgebra::*;
+
+use crate::build::twist::{create_base, midpoint, Spin, Twist, TwistFace};
+use crate::constants::Real;
+use crate::fabric::Fabric;
+
+const ROOT3: Real = 1.732_050_8;
+const ROOT5: Real = 2.236_068;
+const ROOT6: Real = 2.449_489_7;
+const PHI: Real = (1.0 + ROOT5) / 2.0;
+const CONNECTOR_LENGTH: Real = 0.05;
+const CONFLICT_MULTIPLE: Real = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    PushA,
+    PushB,
+    PullA,
+    PullB,
+    PullAA,
+    Conflict,
+    Connector,
+    Radial,
+    ShapingDistancer,
+    PretenstDistancer,
+}
+
+impl Role {
+    pub fn is_push(self) -> bool {
+        matches!(self, Role::PushA | Role::PushB)
+    }
+
+    pub fn length(self) -> Real {
+        match self {
+            Role::PushA => ROOT6,
+            Role::PushB => PHI * ROOT3,
+            Role::PullB => ROOT3,
+            Role::PullAA => 0.5,
+            Role::Conflict => 0.01,
+            Role::PullA | Role::Connector | Role::Radial | Role::ShapingDistancer | Role::PretenstDistancer => 1.0,
+        }
+    }
+
+    pub fn stiffness(self) -> Real {
+        match self {
+            Role::PullAA => 0.4,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IntervalSpec {
+    pub role: Role,
+    pub scale: Real,
+}
+
+#[derive(Debug, Clone)]
+pub struct RadialPull {
+    pub alpha_face: usize,
+    pub omega_face: usize,
+    pub alpha_joint: usize,
+    pub omega_joint: usize,
+    pub axis: usize,
+    pub rays: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FaceAction {
+    Join,
+    ShapingDistance(Real),
+    PretenstDistance(Real),
+}
+
+/// Bookkeeping on top of a `Fabric` which remembers what every interval is for,
+/// and which twists and faces the joints belong to.
+#[derive(Debug, Clone)]
+pub struct Tensegrity {
+    pub(crate) countdown: Real,
+    pub(crate) specs: Vec<IntervalSpec>,
+    pub(crate) twists: Vec<Twist>,
+    pub(crate) faces: Vec<TwistFace>,
+    pub(crate) connectors: Vec<RadialPull>,
+    pub(crate) distancers: Vec<RadialPull>,
+}
+
+impl Tensegrity {
+    pub fn new(countdown: Real) -> Tensegrity {
+        Tensegrity {
+            countdown,
+            specs: Vec::new(),
+            twists: Vec::new(),
+            faces: Vec::new(),
+            connectors: Vec::new(),
+            distancers: Vec::new(),
+        }
+    }
+
+    pub fn live_faces(&self) -> impl Iterator<Item=usize> + '_ {
+        (0..self.faces.len()).filter(move |&index| !self.faces[index].removed)
+    }
+
+    pub fn marked_faces(&self, mark_name: &str) -> Vec<usize> {
+        self.live_faces()
+            .filter(|&index| self.faces[index].marks.iter().any(|mark| mark == mark_name))
+            .collect()
+    }
+
+    pub fn create_interval(&mut self, fabric: &mut Fabric, alpha: usize, omega: usize, role: Role, scale: Real, patience: Real) -> usize {
+        let target_length = role.length() * scale;
+        let current_length = if target_length == 0.0 { 0.0 } else { distance(fabric, alpha, omega) };
+        let countdown = self.countdown * (target_length - current_length).abs() * patience;
+        self.add_interval(fabric, alpha, omega, IntervalSpec { role, scale }, current_length, target_length, countdown)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_interval(&mut self, fabric: &mut Fabric, alpha: usize, omega: usize, spec: IntervalSpec, length_0: Real, length_1: Real, countdown: Real) -> usize {
+        let attack = if countdown <= 0.0 { 0.0 } else { 1.0 / countdown };
+        let index = fabric.create_interval(alpha, omega, spec.role.is_push(), length_0, length_1, spec.role.stiffness(), attack);
+        self.specs.push(spec);
+        index
+    }
+
+    /// Remove intervals from the fabric, keeping every remembered index valid.
+    pub fn remove_intervals(&mut self, fabric: &mut Fabric, mut indices: Vec<usize>) {
+        indices.sort_unstable();
+        indices.dedup();
+        for &index in indices.iter().rev() {
+            fabric.remove_interval(index);
+            self.forget_interval(index);
+        }
+    }
+
+    /// Drop what is remembered about an interval already gone from the fabric, and move the
+    /// indices after it down.
+    pub(crate) fn forget_interval(&mut self, index: usize) {
+        self.specs.remove(index);
+        let shift = |interval: &mut usize| {
+            if *interval > index {
+                *interval -= 1
+            }
+        };
+        for face in &mut self.faces {
+            face.pulls.retain(|&pull| pull != index);
+            face.pulls.iter_mut().for_each(shift);
+        }
+        for radial in self.connectors.iter_mut().chain(self.distancers.iter_mut()) {
+            shift(&mut radial.axis);
+            radial.rays.iter_mut().for_each(shift);
+        }
+    }
+
+    /// Remove joints from the fabric, keeping every remembered index valid.
+    /// The intervals attached to these joints must already be gone.
+    pub fn remove_joints(&mut self, fabric: &mut Fabric, mut indices: Vec<usize>) {
+        indices.sort_unstable();
+        indices.dedup();
+        for &index in indices.iter().rev() {
+            fabric.remove_joint(index);
+            let shift = |joint: &mut usize| {
+                if *joint > index {
+                    *joint -= 1
+                }
+            };
+            for face in &mut self.faces {
+                face.ends.iter_mut().for_each(shift);
+                face.middle.iter_mut().for_each(shift);
+            }
+            for radial in self.connectors.iter_mut().chain(self.distancers.iter_mut()) {
+                shift(&mut radial.alpha_joint);
+                shift(&mut radial.omega_joint);
+            }
+        }
+    }
+
+    pub fn remove_face(&mut self, fabric: &mut Fabric, face_index: usize) {
+        let pulls = std::mem::take(&mut self.faces[face_index].pulls);
+        self.remove_intervals(fabric, pulls);
+        if let Some(middle) = self.faces[face_index].middle.take() {
+            self.remove_joints(fabric, vec![middle]);
+        }
+        if let Some(removed) = self.faces[face_index].fabric_face.take() {
+            fabric.remove_face(removed);
+            for face in &mut self.faces {
+                if let Some(fabric_face) = face.fabric_face.as_mut() {
+                    if *fabric_face > removed {
+                        *fabric_face -= 1;
+                    }
+                }
+            }
+        }
+        self.faces[face_index].removed = true;
+    }
+
+    pub fn create_loop(&mut self, fabric: &mut Fabric, face_a: usize, face_b: usize) {
+        let mut reverse_a = self.faces[face_a].ends;
+        reverse_a.reverse();
+        let forward_b = self.faces[face_b].ends;
+        let scale = (self.faces[face_a].scale + self.faces[face_b].scale) / 2.0;
+        for index in 0..reverse_a.len() {
+            let a0 = reverse_a[index];
+            let a1 = reverse_a[(index + 1) % reverse_a.len()];
+            let b = forward_b[index];
+            self.create_interval(fabric, a0, b, Role::PullA, scale, 1.0);
+            self.create_interval(fabric, b, a1, Role::PullA, scale, 1.0);
+        }
+        self.remove_face(fabric, face_b);
+        self.remove_face(fabric, face_a);
+    }
+
+    pub fn face_to_triangle(&mut self, fabric: &mut Fabric, face_index: usize) {
+        let pulls = std::mem::take(&mut self.faces[face_index].pulls);
+        self.remove_intervals(fabric, pulls);
+        if let Some(middle) = self.faces[face_index].middle.take() {
+            self.remove_joints(fabric, vec![middle]);
+        }
+        let TwistFace { ends, scale, .. } = self.faces[face_index];
+        for index in 0..ends.len() {
+            let pull = self.create_interval(fabric, ends[index], ends[(index + 1) % ends.len()], Role::PullB, scale, 1.0);
+            self.faces[face_index].pulls.push(pull);
+        }
+    }
+
+    pub fn triangle_faces(&mut self, fabric: &mut Fabric) {
+        let faces: Vec<usize> = self.live_faces().collect();
+        for face in faces {
+            self.face_to_triangle(fabric, face);
+        }
+    }
+
+    pub fn create_radial_pulls(&mut self, fabric: &mut Fabric, faces: &[usize], action: FaceAction) {
+        match action {
+            FaceAction::ShapingDistance(pull_scale) | FaceAction::PretenstDistance(pull_scale) => {
+                let role = if let FaceAction::ShapingDistance(_) = action {
+                    Role::ShapingDistancer
+                } else {
+                    Role::PretenstDistancer
+                };
+                for (index_a, &face_a) in faces.iter().enumerate() {
+                    for &face_b in &faces[..index_a] {
+                        self.create_radial_pull(fabric, face_a, face_b, role, Some(pull_scale));
+                    }
+                }
+            }
+            FaceAction::Join => match faces {
+                &[face_a, face_b] if self.faces[face_a].spin != self.faces[face_b].spin => {
+                    self.create_radial_pull(fabric, face_a, face_b, Role::Connector, None);
+                }
+                [_, _] | [_, _, _] => self.center_twist_connectors(fabric, faces),
+                _ => {}
+            },
+        }
+    }
+
+    fn center_twist_connectors(&mut self, fabric: &mut Fabric, faces: &[usize]) {
+        let scale = faces.iter().map(|&face| self.faces[face].scale).sum::<Real>() / faces.len() as Real;
+        let locations: Vec<Vector3<Real>> = faces.iter().map(|&face| self.faces[face].location(fabric)).collect();
+        let omni_twist = self.create_twist(fabric, Spin::LeftRight, scale, create_base(midpoint(&locations)));
+        for (&face, location) in faces.iter().zip(locations.iter()) {
+            let spin = self.faces[face].spin;
+            let closest = self.twists[omni_twist].faces
+                .iter()
+                .copied()
+                .filter(|&opposing| !self.faces[opposing].pulls.is_empty() && self.faces[opposing].spin != spin)
+                .min_by(|&a, &b| {
+                    let distance_a = (self.faces[a].location(fabric) - location).magnitude();
+                    let distance_b = (self.faces[b].location(fabric) - location).magnitude();
+                    distance_a.total_cmp(&distance_b)
+                });
+            if let Some(closest) = closest {
+                self.create_radial_pull(fabric, closest, face, Role::Connector, None);
+            }
+        }
+    }
+
+    fn create_radial_pull(&mut self, fabric: &mut Fabric, alpha_face: usize, omega_face: usize, role: Role, pull_scale: Option<Real>) {
+        let alpha_location = self.faces[alpha_face].location(fabric);
+        let omega_location = self.faces[omega_face].location(fabric);
+        let alpha_joint = fabric.create_joint(alpha_location.x, alpha_location.y, alpha_location.z);
+        let omega_joint = fabric.create_joint(omega_location.x, omega_location.y, omega_location.z);
+        let ideal_length = distance(fabric, alpha_joint, omega_joint);
+        let rest_length = match pull_scale {
+            Some(pull_scale) => pull_scale * ideal_length,
+            None => CONNECTOR_LENGTH / 2.0,
+        };
+        let countdown = self.countdown * (rest_length - ideal_length).abs();
+        let axis = self.add_interval(fabric, alpha_joint, omega_joint, IntervalSpec { role, scale: 1.0 }, ideal_length, rest_length, countdown);
+        let mut rays = Vec::new();
+        for (joint, face) in [(alpha_joint, alpha_face), (omega_joint, omega_face)] {
+            let ends = self.faces[face].ends;
+            let ray_length = ends.iter().map(|&end| distance(fabric, joint, end)).sum::<Real>() / ends.len() as Real;
+            for end in ends {
+                let ideal_length = distance(fabric, joint, end);
+                let countdown = self.countdown * (ray_length - ideal_length).abs();
+                let spec = IntervalSpec { role: Role::Radial, scale: ray_length };
+                rays.push(self.add_interval(fabric, joint, end, spec, ideal_length, ray_length, countdown));
+            }
+        }
+        let radial_pull = RadialPull { alpha_face, omega_face, alpha_joint, omega_joint, axis, rays };
+        if role == Role::Connector {
+            self.connectors.push(radial_pull);
+        } else {
+            self.distancers.push(radial_pull);
+        }
+    }
+
+    fn remove_radial_pull(&mut self, fabric: &mut Fabric, radial_pull: RadialPull) {
+        let RadialPull { alpha_joint, omega_joint, axis, mut rays, .. } = radial_pull;
+        rays.push(axis);
+        self.remove_intervals(fabric, rays);
+        self.remove_joints(fabric, vec![alpha_joint, omega_joint]);
+    }
+
+    /// Connect the faces of every connector that has pulled close enough.
+    /// Returns true while connectors are still waiting.
+    pub fn check_connectors(&mut self, fabric: &mut Fabric) -> bool {
+        while let Some(index) = self.connectors.iter().position(|connector| {
+            distance(fabric, connector.alpha_joint, connector.omega_joint) <= CONNECTOR_LENGTH
+        }) {
+            let connector = self.connectors.remove(index);
+            let (alpha_face, omega_face) = (connector.alpha_face, connector.omega_face);
+            self.remove_radial_pull(fabric, connector);
+            self.rotate_for_best_ring(fabric, alpha_face, omega_face);
+            self.create_loop(fabric, alpha_face, omega_face);
+        }
+        !self.connectors.is_empty()
+    }
+
+    /// Remove the distancers with the given role, which is either the shaping or the pretenst one.
+    pub fn remove_distancers(&mut self, fabric: &mut Fabric, role: Role) {
+        while let Some(index) = self.distancers.iter().position(|distancer| self.specs[distancer.axis].role == role) {
+            let distancer = self.distancers.remove(index);
+            self.remove_radial_pull(fabric, distancer);
+        }
+    }
+
+    fn rotate_for_best_ring(&mut self, fabric: &Fabric, alpha_face: usize, omega_face: usize) {
+        let mut alpha_ends = self.faces[alpha_face].ends;
+        alpha_ends.reverse();
+        let omega_ends = self.faces[omega_face].ends;
+        let count = alpha_ends.len();
+        let ring_length = |rotation: usize| -> Real {
+            (0..count)
+                .map(|walk| {
+                    let omega = omega_ends[(walk + rotation) % count];
+                    distance(fabric, alpha_ends[walk], omega) + distance(fabric, omega, alpha_ends[(walk + 1) % count])
+                })
+                .sum()
+        };
+        let best_rotation = (0..count)
+            .min_by(|&a, &b| ring_length(a).total_cmp(&ring_length(b)))
+            .unwrap_or(0);
+        self.faces[omega_face].ends = [0, 1, 2].map(|index| omega_ends[(index + best_rotation) % count]);
+    }
+
+    pub fn push_across(&self, fabric: &Fabric, joint: usize) -> Option<usize> {
+        fabric.intervals
+            .iter()
+            .zip(self.specs.iter())
+            .filter(|(_, spec)| spec.role.is_push())
+            .find_map(|(interval, _)| other_joint(interval.alpha_index, interval.omega_index, joint))
+    }
+
+    pub fn create_conflict_pulls(&mut self, fabric: &mut Fabric) {
+        let across: Vec<Option<usize>> = (0..fabric.joints.len())
+            .map(|joint| self.push_across(fabric, joint))
+            .collect();
+        let location = |joint: usize| fabric.joints[joint].location.coords;
+        let between = |joint: usize, alpha: usize, omega: usize| {
+            let to_alpha = (location(joint) - location(alpha)).normalize();
+            let to_omega = (location(joint) - location(omega)).normalize();
+            to_alpha.dot(&to_omega) < 0.0
+        };
+        let mut conflicts = Vec::new();
+        for (joint_a, other_a) in across.iter().enumerate() {
+            let Some(other_a) = *other_a else { continue; };
+            for (joint_b, other_b) in across.iter().enumerate().skip(joint_a + 1) {
+                let Some(other_b) = *other_b else { continue; };
+                let distance_near = distance(fabric, joint_a, joint_b);
+                let distance_far = distance(fabric, other_a, other_b);
+                if distance_near * CONFLICT_MULTIPLE > distance_far {
+                    continue;
+                }
+                if between(joint_a, joint_b, other_b) && between(joint_b, joint_a, other_a) {
+                    conflicts.push((joint_a, joint_b));
+                }
+            }
+        }
+        for (joint_a, joint_b) in conflicts {
+            self.create_interval(fabric, joint_a, joint_b, Role::Conflict, 1.0, 1.0);
+        }
+    }
+
+    /// Stand the fabric on the marked faces, which end up underneath and facing down.
+    pub fn orient(&self, fabric: &mut Fabric, faces: &[usize]) {
+        if faces.is_empty() {
+            return;
+        }
+        let position = faces.iter().map(|&face| self.faces[face].location(fabric)).sum::<Vector3<Real>>() / faces.len() as Real;
+        let upwards = -faces.iter().map(|&face| self.faces[face].normal(fabric)).sum::<Vector3<Real>>().normalize();
+        let (b1, b2) = basis_from_vector(&upwards);
+        let basis = Matrix4::new(
+            b1.x, upwards.x, b2.x, position.x,
+            b1.y, upwards.y, b2.y, position.y,
+            b1.z, upwards.z, b2.z, position.z,
+            0.0, 0.0, 0.0, 1.0,
+        );
+        if let Some(inverse) = basis.try_inverse() {
+            fabric.apply_matrix4(inverse.as_slice());
+            fabric.set_altitude(5.0);
+        }
+    }
+}
+
+pub fn distance(fabric: &Fabric, alpha: usize, omega: usize) -> Real {
+    (fabric.joints[omega].location - fabric.joints[alpha].location).magnitude()
+}
+
+pub fn other_joint(alpha: usize, omega: usize, joint: usize) -> Option<usize> {
+    if alpha == joint {
+        Some(omega)
+    } else if omega == joint {
+        Some(alpha)
+    } else {
+        None
+    }
+}
+
+fn basis_from_vector(up: &Vector3<Real>) -> (Vector3<Real>, Vector3<Real>) {
+    let (x, y, z) = (up.x, up.y, up.z);
+    let xy = x * x + y * y;
+    let yz = y * y + z * z;
+    let zx = z * z + x * x;
+    let b1 = if xy > yz && xy > zx {
+        Vector3::new(-y, x, 0.0)
+    } else if yz > xy && yz > zx {
+        Vector3::new(0.0, -z, y)
+    } else {
+        Vector3::new(-z, 0.0, x)
+    }.normalize();
+    let b2 = up.cross(&b1).normalize();
+    (b1, b2)
+}
@@ -0,0 +1,262 @@
+use crate::constants::real_consts::PI;
+use crate::constants::Real;
+
+use nalgebra::*;
+
+use crate::build::tensegrity::{Role, Tensegrity};
+use crate::fabric::Fabric;
+use crate::tenscript::{FaceName, SeedType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spin {
+    Left,
+    Right,
+    LeftRight,
+    RightLeft,
+}
+
+impl Spin {
+    pub fn from_seed(seed: SeedType) -> Spin {
+        match seed {
+            SeedType::Left => Spin::Left,
+            SeedType::Right => Spin::Right,
+            SeedType::LeftRight => Spin::LeftRight,
+            SeedType::RightLeft => Spin::RightLeft,
+        }
+    }
+
+    pub fn is_omni(self) -> bool {
+        matches!(self, Spin::LeftRight | Spin::RightLeft)
+    }
+
+    pub fn change(self, opposite: bool, to_omni: bool) -> Spin {
+        match (self, opposite, to_omni) {
+            (Spin::Left, true, true) => Spin::RightLeft,
+            (Spin::Left, true, false) => Spin::Right,
+            (Spin::Right, true, true) => Spin::LeftRight,
+            (Spin::Right, true, false) => Spin::Left,
+            (Spin::LeftRight, true, _) => Spin::RightLeft,
+            (Spin::RightLeft, true, _) => Spin::LeftRight,
+            (Spin::Left, false, true) => Spin::LeftRight,
+            (Spin::Right, false, true) => Spin::RightLeft,
+            (spin, false, _) => spin,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TwistFace {
+    pub spin: Spin,
+    pub scale: Real,
+    pub ends: [usize; 3],
+    pub middle: Option<usize>,
+    pub pulls: Vec<usize>,
+    pub marks: Vec<String>,
+    pub removed: bool,
+    pub(crate) fabric_face: Option<usize>,
+}
+
+impl TwistFace {
+    pub fn location(&self, fabric: &Fabric) -> Vector3<Real> {
+        midpoint(&self.end_locations(fabric))
+    }
+
+    pub fn normal(&self, fabric: &Fabric) -> Vector3<Real> {
+        points_to_normal(&self.end_locations(fabric))
+    }
+
+    pub fn end_locations(&self, fabric: &Fabric) -> [Vector3<Real>; 3] {
+        self.ends.map(|end| fabric.joints[end].location.coords)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Twist {
+    pub faces: Vec<usize>,
+}
+
+impl Twist {
+    pub fn face(&self, face_name: FaceName) -> Option<usize> {
+        let index = match (self.faces.len(), face_name) {
+            (2, FaceName::Aminus) => 0,
+            (2, FaceName::Aplus) => 1,
+            (8, FaceName::Aminus) => 0,
+            (8, FaceName::Bplus) => 2,
+            (8, FaceName::Cplus) => 1,
+            (8, FaceName::Dplus) => 3,
+            (8, FaceName::Bminus) => 4,
+            (8, FaceName::Cminus) => 5,
+            (8, FaceName::Dminus) => 6,
+            (8, FaceName::Aplus) => 7,
+            _ => return None,
+        };
+        Some(self.faces[index])
+    }
+}
+
+struct PointPair {
+    alpha: Vector3<Real>,
+    omega: Vector3<Real>,
+}
+
+impl Tensegrity {
+    pub fn create_twist(&mut self, fabric: &mut Fabric, spin: Spin, scale: Real, base: [Vector3<Real>; 3]) -> usize {
+        let twist = match spin {
+            Spin::Left => self.create_single(fabric, base, spin, true, scale),
+            Spin::Right => self.create_single(fabric, base, spin, false, scale),
+            Spin::LeftRight => self.create_double(fabric, base, true, scale),
+            Spin::RightLeft => self.create_double(fabric, base, false, scale),
+        };
+        self.twists.push(twist);
+        self.twists.len() - 1
+    }
+
+    pub fn create_twist_on(&mut self, fabric: &mut Fabric, base_face: usize, spin: Spin, scale: Real) -> usize {
+        let mut base = self.faces[base_face].end_locations(fabric);
+        base.reverse();
+        let twist = self.create_twist(fabric, spin, scale, base);
+        let new_base = self.twists[twist].face(FaceName::Aminus).unwrap();
+        self.create_loop(fabric, base_face, new_base);
+        twist
+    }
+
+    fn create_single(&mut self, fabric: &mut Fabric, base: [Vector3<Real>; 3], spin: Spin, left_spin: bool, scale: Real) -> Twist {
+        let pairs = point_pairs(&base, scale, left_spin);
+        let ends: Vec<(usize, usize)> = pairs
+            .iter()
+            .map(|PointPair { alpha, omega }| (create_joint(fabric, alpha), create_joint(fabric, omega)))
+            .collect();
+        let alpha_joint = create_joint(fabric, &midpoint(&pairs.iter().map(|pair| pair.alpha).collect::<Vec<_>>()));
+        let omega_joint = create_joint(fabric, &midpoint(&pairs.iter().map(|pair| pair.omega).collect::<Vec<_>>()));
+        for &(alpha, omega) in &ends {
+            self.create_interval(fabric, alpha, omega, Role::PushA, scale, 1.0);
+        }
+        let mut twist = Twist { faces: Vec::new() };
+        let alphas = [ends[0].0, ends[1].0, ends[2].0];
+        let omegas = [ends[2].1, ends[1].1, ends[0].1];
+        twist.faces.push(self.create_face(fabric, alphas, alpha_joint, spin, scale));
+        twist.faces.push(self.create_face(fabric, omegas, omega_joint, spin, scale));
+        for (index, &(alpha, _)) in ends.iter().enumerate() {
+            let offset = if left_spin { ends.len() - 1 } else { 1 };
+            let (_, omega) = ends[(index + offset) % ends.len()];
+            self.create_interval(fabric, alpha, omega, Role::PullB, scale, 1.0);
+        }
+        twist
+    }
+
+    fn create_double(&mut self, fabric: &mut Fabric, base: [Vector3<Real>; 3], left_spin: bool, scale: Real) -> Twist {
+        let bot_pairs = point_pairs(&base, scale, left_spin);
+        let top_base = [bot_pairs[0].omega, bot_pairs[1].omega, bot_pairs[2].omega];
+        let top_pairs = point_pairs(&top_base, scale, !left_spin);
+        let bot: Vec<(usize, usize)> = bot_pairs
+            .iter()
+            .map(|PointPair { alpha, omega }| (create_joint(fabric, alpha), create_joint(fabric, omega)))
+            .collect();
+        let top: Vec<(usize, usize)> = top_pairs
+            .iter()
+            .map(|PointPair { alpha, omega }| (create_joint(fabric, alpha), create_joint(fabric, omega)))
+            .collect();
+        for &(alpha, omega) in bot.iter().chain(top.iter()) {
+            self.create_interval(fabric, alpha, omega, Role::PushB, scale, 1.0);
+        }
+        let (a, o) = (|i: usize| bot[i].0, |i: usize| bot[i].1);
+        let (ta, to) = (|i: usize| top[i].0, |i: usize| top[i].1);
+        let face_joints: [[usize; 3]; 8] = if left_spin {
+            [
+                [a(0), a(1), a(2)],
+                [a(2), o(1), ta(1)],
+                [a(0), o(2), ta(2)],
+                [a(1), o(0), ta(0)],
+                [o(1), ta(0), to(1)],
+                [o(0), ta(2), to(0)],
+                [o(2), ta(1), to(2)],
+                [to(2), to(1), to(0)],
+            ]
+        } else {
+            [
+                [a(0), a(1), a(2)],
+                [ta(2), o(0), a(2)],
+                [ta(0), o(1), a(0)],
+                [ta(1), o(2), a(1)],
+                [to(1), ta(2), o(2)],
+                [to(2), ta(0), o(0)],
+                [to(0), ta(1), o(1)],
+                [to(2), to(1), to(0)],
+            ]
+        };
+        let middles: Vec<usize> = face_joints
+            .iter()
+            .map(|joints| {
+                let locations = joints.map(|joint| fabric.joints[joint].location.coords);
+                create_joint(fabric, &midpoint(&locations))
+            })
+            .collect();
+        let mut twist = Twist { faces: Vec::new() };
+        for (index, (&ends, &middle)) in face_joints.iter().zip(middles.iter()).enumerate() {
+            let spin = if left_spin == [0, 4, 5, 6].contains(&index) { Spin::Left } else { Spin::Right };
+            twist.faces.push(self.create_face(fabric, ends, middle, spin, scale));
+        }
+        twist
+    }
+
+    fn create_face(&mut self, fabric: &mut Fabric, ends: [usize; 3], middle: usize, spin: Spin, scale: Real) -> usize {
+        let pulls = ends
+            .iter()
+            .map(|&end| self.create_interval(fabric, end, middle, Role::PullA, scale, 1.0))
+            .collect();
+        let fabric_face = fabric.create_face(ends[0], ends[1], ends[2]);
+        self.faces.push(TwistFace {
+            spin,
+            scale,
+            ends,
+            middle: Some(middle),
+            pulls,
+            marks: Vec::new(),
+            removed: false,
+            fabric_face: Some(fabric_face),
+        });
+        self.faces.len() - 1
+    }
+}
+
+pub fn create_base(location: Vector3<Real>) -> [Vector3<Real>; 3] {
+    [0, 1, 2].map(|index| {
+        let angle = index as Real * PI * 2.0 / 3.0;
+        Vector3::new(angle.cos(), 0.0, angle.sin()) + location
+    })
+}
+
+pub fn midpoint(points: &[Vector3<Real>]) -> Vector3<Real> {
+    points.iter().sum::<Vector3<Real>>() / points.len() as Real
+}
+
+pub fn points_to_normal(points: &[Vector3<Real>]) -> Vector3<Real> {
+    let mid = midpoint(points);
+    let radials: Vec<Vector3<Real>> = points.iter().map(|point| point - mid).collect();
+    let mut normal: Vector3<Real> = zero();
+    for (index, current) in radials.iter().enumerate() {
+        let next = &radials[(index + 1) % radials.len()];
+        normal += current.cross(next).normalize();
+    }
+    normal.normalize()
+}
+
+fn create_joint(fabric: &mut Fabric, location: &Vector3<Real>) -> usize {
+    fabric.create_joint(location.x, location.y, location.z)
+}
+
+fn point_pairs(base: &[Vector3<Real>; 3], scale: Real, left_spin: bool) -> Vec<PointPair> {
+    let count = base.len() as isize;
+    let mid = midpoint(base);
+    let up = points_to_normal(base) * -scale;
+    let from_mid = |index: isize, offset: isize| (base[((index + count + offset) % count) as usize] - mid).normalize();
+    let between = |index: isize, offset_a: isize, offset_b: isize| (from_mid(index, offset_a) + from_mid(index, offset_b)).normalize();
+    (0..count)
+        .map(|index| {
+            let alpha = mid + between(index, 0, 1) * scale;
+            let omega_direction = if left_spin { between(index, 1, 2) } else { between(index, -1, 0) };
+            let omega = mid + up + omega_direction * scale;
+            PointPair { alpha, omega }
+        })
+        .collect()
+}
@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+
+use nalgebra::*;
+
+use crate::build::tensegrity::{other_joint, IntervalSpec, Role, Tensegrity};
+use crate::constants::Real;
+use crate::fabric::Fabric;
+use crate::tenscript::VulcanizeType;
+
+const VULCANIZE_PATIENCE: Real = 5.0;
+
+struct Pair {
+    alpha: usize,
+    omega: usize,
+    spec: IntervalSpec,
+}
+
+/// Which intervals meet at each joint, since `Fabric` only knows the other direction.
+struct Adjacency {
+    pushes: Vec<Option<usize>>,
+    pulls: Vec<Vec<usize>>,
+    existing: HashSet<(usize, usize)>,
+}
+
+impl Adjacency {
+    fn new(tensegrity: &Tensegrity, fabric: &Fabric) -> Adjacency {
+        let mut pushes = vec![None; fabric.joints.len()];
+        let mut pulls = vec![Vec::new(); fabric.joints.len()];
+        let mut existing = HashSet::new();
+        for (index, (interval, spec)) in fabric.intervals.iter().zip(tensegrity.specs.iter()).enumerate() {
+            for joint in [interval.alpha_index, interval.omega_index] {
+                if spec.role.is_push() {
+                    pushes[joint] = Some(index);
+                } else {
+                    pulls[joint].push(index);
+                }
+            }
+            existing.insert(key(interval.alpha_index, interval.omega_index));
+        }
+        Adjacency { pushes, pulls, existing }
+    }
+
+    fn has_push(&self, joint: usize) -> bool {
+        self.pushes[joint].is_some()
+    }
+
+    fn across_push(&self, fabric: &Fabric, joint: usize) -> Option<usize> {
+        let push = &fabric.intervals[self.pushes[joint]?];
+        other_joint(push.alpha_index, push.omega_index, joint)
+    }
+
+    fn pulls_with_role<'a>(&'a self, tensegrity: &'a Tensegrity, joint: usize, role: Role) -> impl Iterator<Item=usize> + 'a {
+        self.pulls[joint]
+            .iter()
+            .copied()
+            .filter(move |&pull| tensegrity.specs[pull].role == role)
+    }
+
+    fn across_pulls(&self, tensegrity: &Tensegrity, fabric: &Fabric, joint: usize, role: Role) -> Vec<usize> {
+        self.pulls_with_role(tensegrity, joint, role)
+            .filter_map(|pull| {
+                let interval = &fabric.intervals[pull];
+                other_joint(interval.alpha_index, interval.omega_index, joint)
+            })
+            .collect()
+    }
+
+    fn joins(&self, fabric: &Fabric, joint: usize, other: usize) -> bool {
+        self.pulls[joint].iter().any(|&pull| {
+            let interval = &fabric.intervals[pull];
+            key(interval.alpha_index, interval.omega_index) == key(joint, other)
+        })
+    }
+
+    fn add(&mut self, pairs: &mut Vec<Pair>, pair: Pair) {
+        if self.existing.insert(key(pair.alpha, pair.omega)) {
+            pairs.push(pair);
+        }
+    }
+}
+
+impl Tensegrity {
+    pub fn vulcanize(&mut self, fabric: &mut Fabric, vulcanize_type: VulcanizeType) {
+        let pairs = match vulcanize_type {
+            VulcanizeType::Bowtie => self.bowtie_pairs(fabric),
+            VulcanizeType::Snelson => self.snelson_pairs(fabric),
+        };
+        for Pair { alpha, omega, spec } in pairs {
+            self.create_interval(fabric, alpha, omega, spec.role, spec.scale, VULCANIZE_PATIENCE);
+        }
+        if let VulcanizeType::Snelson = vulcanize_type {
+            self.triangle_faces(fabric);
+        }
+    }
+
+    fn snelson_pairs(&self, fabric: &Fabric) -> Vec<Pair> {
+        let mut adjacency = Adjacency::new(self, fabric);
+        let mut pairs = Vec::new();
+        let pull_bs: Vec<usize> = (0..self.specs.len())
+            .filter(|&index| self.specs[index].role == Role::PullB)
+            .collect();
+        for pull_b in pull_bs {
+            let interval = &fabric.intervals[pull_b];
+            for (alpha, b) in [(interval.alpha_index, interval.omega_index), (interval.omega_index, interval.alpha_index)] {
+                let Some(a) = adjacency.across_push(fabric, alpha) else { continue; };
+                if !adjacency.has_push(b) {
+                    continue;
+                }
+                let across_b = adjacency.across_pulls(self, fabric, b, Role::PullA);
+                let omega = adjacency.across_pulls(self, fabric, a, Role::PullA)
+                    .into_iter()
+                    .find(|joint| across_b.contains(joint));
+                let Some(omega) = omega else { continue; };
+                if !adjacency.has_push(omega) {
+                    continue;
+                }
+                let spec = IntervalSpec { role: Role::PullB, scale: self.specs[pull_b].scale };
+                adjacency.add(&mut pairs, Pair { alpha, omega, spec });
+            }
+        }
+        pairs
+    }
+
+    fn bowtie_pairs(&self, fabric: &Fabric) -> Vec<Pair> {
+        let mut adjacency = Adjacency::new(self, fabric);
+        let mut pairs = Vec::new();
+        let common = |adjacency: &Adjacency, a: usize, b: usize| -> Option<usize> {
+            let across_b = adjacency.across_pulls(self, fabric, b, Role::PullA);
+            adjacency.across_pulls(self, fabric, a, Role::PullA)
+                .into_iter()
+                .find(|joint| across_b.contains(joint))
+        };
+        let next_pair = |adjacency: &Adjacency, near: usize| -> Option<Pair> {
+            let pull_b = adjacency.pulls_with_role(self, near, Role::PullB).next()?;
+            let far = other_joint(fabric.intervals[pull_b].alpha_index, fabric.intervals[pull_b].omega_index, near)?;
+            let other_far = adjacency.across_push(fabric, near)?;
+            let other_b = adjacency.pulls_with_role(self, other_far, Role::PullB).next()?;
+            let other_near = other_joint(fabric.intervals[other_b].alpha_index, fabric.intervals[other_b].omega_index, other_far)?;
+            let common_near = common(adjacency, near, other_near)?;
+            let common_far = common(adjacency, far, other_far)?;
+            let (near_push, far_push) = (adjacency.has_push(common_near), adjacency.has_push(common_far));
+            if near_push && !far_push {
+                let across_far = adjacency.across_push(fabric, far)?;
+                if !adjacency.joins(fabric, across_far, common_near) {
+                    return None;
+                }
+            } else if far_push && !near_push {
+                let across_near = adjacency.across_push(fabric, near)?;
+                if !adjacency.joins(fabric, across_near, common_far) {
+                    return None;
+                }
+            }
+            let alpha = if near_push { common_near } else { near };
+            let omega = if far_push { common_far } else { far };
+            let role = if near_push && far_push { Role::PullA } else { Role::PullB };
+            let spec = IntervalSpec { role, scale: self.specs[pull_b].scale };
+            Some(Pair { alpha, omega, spec })
+        };
+        let pull_bs: Vec<usize> = (0..self.specs.len())
+            .filter(|&index| self.specs[index].role == Role::PullB)
+            .collect();
+        for pull_b in pull_bs {
+            for near in [fabric.intervals[pull_b].alpha_index, fabric.intervals[pull_b].omega_index] {
+                if let Some(pair) = next_pair(&adjacency, near) {
+                    adjacency.add(&mut pairs, pair);
+                }
+            }
+        }
+        let location = |joint: usize| fabric.joints[joint].location.coords;
+        let three_pull_pushes: Vec<usize> = (0..fabric.joints.len())
+            .filter(|&joint| adjacency.has_push(joint) && adjacency.pulls_with_role(self, joint, Role::PullA).count() == 3)
+            .collect();
+        for joint in three_pull_pushes {
+            let found = adjacency.pulls_with_role(self, joint, Role::PullA).find(|&pull| {
+                let interval = &fabric.intervals[pull];
+                other_joint(interval.alpha_index, interval.omega_index, joint)
+                    .is_some_and(|other| !adjacency.has_push(other))
+            });
+            let Some(found) = found else { continue; };
+            let interval = &fabric.intervals[found];
+            let Some(face_joint) = other_joint(interval.alpha_index, interval.omega_index, joint) else { continue; };
+            let outwards = |end: usize| -> (usize, Vector3<Real>) { (end, (location(end) - location(joint)).normalize()) };
+            let joint_ends: Vec<_> = adjacency.across_pulls(self, fabric, joint, Role::PullA).into_iter().map(outwards).collect();
+            let face_ends: Vec<_> = adjacency.across_pulls(self, fabric, face_joint, Role::PullA)
+                .into_iter()
+                .filter(|&end| end != joint)
+                .map(outwards)
+                .collect();
+            for (end, direction) in joint_ends {
+                let best = face_ends
+                    .iter()
+                    .max_by(|(_, a), (_, b)| direction.dot(a).total_cmp(&direction.dot(b)));
+                if let Some(&(omega, _)) = best {
+                    let spec = IntervalSpec { role: Role::PullAA, scale: self.specs[found].scale };
+                    adjacency.add(&mut pairs, Pair { alpha: end, omega, spec });
+                }
+            }
+        }
+        pairs
+    }
+}
+
+fn key(alpha: usize, omega: usize) -> (usize, usize) {
+    if alpha < omega { (alpha, omega) } else { (omega, alpha) }
+}
@@ -0,0 +1,270 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::collections::HashMap;
+
+use nalgebra::*;
+
+use crate::constants::*;
+use crate::fabric::Fabric;
+use crate::interval::Interval;
+use crate::world::World;
+
+/// The hash cells are never smaller than this, since pushes of no length and no radius would
+/// otherwise leave nothing to divide space by and every point would land in a cell at infinity.
+const SMALLEST_CELL: Real = 1e-3;
+
+/// Two push intervals which, as bars of the world's bar radius, pass into each other. Joints are
+/// the rounded ends of the bars, so joints that come too close show up here as their bars.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contact {
+    /// The intervals, lower index first.
+    pub intervals: (usize, usize),
+    /// Where along each interval, from alpha at zero to omega at one, they come closest.
+    pub along: (Real, Real),
+    /// The direction from the first interval to the second where they come closest.
+    pub normal: Vector3<Real>,
+    /// How far the bars overlap.
+    pub depth: Real,
+}
+
+impl Fabric {
+    /// The contacts found in the last tick, when the world's collision mode is not off.
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    /// Every pair of pushes that clash if they are bars of the given radius, except pairs that
+    /// share a joint since they always touch there.
+    pub fn detect_contacts(&self, bar_radius: Real) -> Vec<Contact> {
+        let pushes: Vec<usize> = (0..self.intervals.len())
+            .filter(|&index| self.intervals[index].push)
+            .collect();
+        let longest = pushes
+            .iter()
+            .map(|&index| self.intervals[index].calculate_current_length(&self.joints))
+            .fold(0.0, Real::max);
+        let mut hash = SpatialHash::new(longest + 2.0 * bar_radius);
+        for &index in &pushes {
+            let (alpha, omega) = self.ends(&self.intervals[index]);
+            let reach = Vector3::repeat(bar_radius);
+            hash.insert(index, alpha.inf(&omega) - reach, alpha.sup(&omega) + reach);
+        }
+        hash.candidates()
+            .into_iter()
+            .filter(|&(first, second)| !self.intervals[first].touches(&self.intervals[second]))
+            .filter_map(|(first, second)| {
+                let (alpha, omega) = self.ends(&self.intervals[first]);
+                let (other_alpha, other_omega) = self.ends(&self.intervals[second]);
+                let (along, closest, other_along, other_closest) =
+                    closest_points(alpha, omega, other_alpha, other_omega);
+                let between = other_closest - closest;
+                let distance = between.magnitude();
+                let depth = 2.0 * bar_radius - distance;
+                if depth <= 0.0 {
+                    return None;
+                }
+                let normal = if distance > 0.0 {
+                    between / distance
+                } else {
+                    crossing_normal(omega - alpha, other_omega - other_alpha)
+                };
+                Some(Contact { intervals: (first, second), along: (along, other_along), normal, depth })
+            })
+            .collect()
+    }
+
+    /// Push clashing bars apart as hard as the softer of the two would push back if it were
+    /// squeezed by the overlap, spread over the joints by how close to each end they touch.
+    pub(crate) fn repel_contacts(&mut self, world: &World) {
+        let contacts = self.detect_contacts(world.bar_radius);
+        for contact in &contacts {
+            let (first, second) = contact.intervals;
+            let stiffness = [first, second]
+                .iter()
+                .map(|&index| {
+                    let interval = &self.intervals[index];
+                    interval.rigidity(world, self.stage) / interval.calculate_current_length(&self.joints)
+                })
+                .fold(Real::INFINITY, Real::min);
+            let force = contact.normal * (stiffness * contact.depth);
+            for (index, along, force) in [(first, contact.along.0, -force), (second, contact.along.1, force)] {
+                let interval = &self.intervals[index];
+                let (alpha, omega) = (interval.alpha_index, interval.omega_index);
+                self.joints[alpha].force += force * (1.0 - along);
+                self.joints[omega].force += force * along;
+            }
+        }
+        self.contacts = contacts;
+    }
+
+    fn ends(&self, interval: &Interval) -> (Point3<Real>, Point3<Real>) {
+        (self.joints[interval.alpha_index].location, self.joints[interval.omega_index].location)
+    }
+}
+
+/// The pushes filed under every cell of a grid that their bounding boxes overlap. With cells at
+/// least as big as the biggest box, each push lands in at most eight of them.
+struct SpatialHash {
+    cell_size: Real,
+    cells: HashMap<[i32; 3], Vec<usize>>,
+}
+
+impl SpatialHash {
+    fn new(cell_size: Real) -> SpatialHash {
+        SpatialHash { cell_size: cell_size.max(SMALLEST_CELL), cells: HashMap::new() }
+    }
+
+    fn cell(&self, point: Point3<Real>) -> [i32; 3] {
+        let cell = |value: Real| (value / self.cell_size).floor() as i32;
+        [cell(point.x), cell(point.y), cell(point.z)]
+    }
+
+    fn insert(&mut self, index: usize, min: Point3<Real>, max: Point3<Real>) {
+        let (low, high) = (self.cell(min), self.cell(max));
+        for x in low[0]..=high[0] {
+            for y in low[1]..=high[1] {
+                for z in low[2]..=high[2] {
+                    self.cells.entry([x, y, z]).or_default().push(index);
+                }
+            }
+        }
+    }
+
+    /// Every pair sharing a cell, once, in order.
+    fn candidates(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for members in self.cells.values() {
+            for (at, &first) in members.iter().enumerate() {
+                for &second in &members[at + 1..] {
+                    pairs.push((first.min(second), first.max(second)));
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+}
+
+/// The closest points of two segments, as fractions along each and as locations.
+fn closest_points(
+    alpha: Point3<Real>,
+    omega: Point3<Real>,
+    other_alpha: Point3<Real>,
+    other_omega: Point3<Real>,
+) -> (Real, Point3<Real>, Real, Point3<Real>) {
+    let (d1, d2, r) = (omega - alpha, other_omega - other_alpha, alpha - other_alpha);
+    let (a, e, f) = (d1.magnitude_squared(), d2.magnitude_squared(), d2.dot(&r));
+    let (s, t) = if a <= Real::EPSILON && e <= Real::EPSILON {
+        (0.0, 0.0)
+    } else if a <= Real::EPSILON {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(&r);
+        if e <= Real::EPSILON {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(&d2);
+            let denominator = a * e - b * b;
+            // parallel segments have no single closest pair, so start from alpha
+            let s = if denominator > 0.0 { ((b * f - c * e) / denominator).clamp(0.0, 1.0) } else { 0.0 };
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+    (s, alpha + d1 * s, t, other_alpha + d2 * t)
+}
+
+/// Some way apart for segments that actually cross, across both when they are not parallel.
+fn crossing_normal(direction: Vector3<Real>, other_direction: Vector3<Real>) -> Vector3<Real> {
+    let across = direction.cross(&other_direction);
+    if across.magnitude_squared() > 0.0 {
+        return across.normalize();
+    }
+    let sideways = if direction.x.abs() < direction.y.abs() { Vector3::x() } else { Vector3::y() };
+    let across = direction.cross(&sideways);
+    if across.magnitude_squared() > 0.0 { across.normalize() } else { Vector3::y() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crossed_pushes(gap: Real) -> Fabric {
+        let mut fabric = Fabric::new(4);
+        let alpha = fabric.create_joint(-1.0, 1.0, 0.0);
+        let omega = fabric.create_joint(1.0, 1.0, 0.0);
+        fabric.create_interval(alpha, omega, true, 2.0, 2.0, 1.0, 0.0);
+        let alpha = fabric.create_joint(0.5, 1.0 + gap, -1.0);
+        let omega = fabric.create_joint(0.5, 1.0 + gap, 1.0);
+        fabric.create_interval(alpha, omega, true, 2.0, 2.0, 1.0, 0.0);
+        fabric
+    }
+
+    #[test]
+    fn finds_bars_that_clash() {
+        let fabric = crossed_pushes(0.05);
+        assert!(fabric.detect_contacts(0.02).is_empty());
+        let contacts = fabric.detect_contacts(0.05);
+        assert_eq!(contacts.len(), 1);
+        let contact = &contacts[0];
+        assert_eq!(contact.intervals, (0, 1));
+        assert!((contact.along.0 - 0.75).abs() < 1e-5 && (contact.along.1 - 0.5).abs() < 1e-5);
+        assert!((contact.normal - Vector3::y()).magnitude() < 1e-5);
+        assert!((contact.depth - 0.05).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ignores_bars_sharing_a_joint() {
+        let mut fabric = Fabric::new(3);
+        let hinge = fabric.create_joint(0.0, 1.0, 0.0);
+        let one = fabric.create_joint(1.0, 1.0, 0.0);
+        let other = fabric.create_joint(1.0, 1.1, 0.0);
+        fabric.create_interval(hinge, one, true, 1.0, 1.0, 1.0, 0.0);
+        fabric.create_interval(hinge, other, true, 1.0, 1.0, 1.0, 0.0);
+        assert!(fabric.detect_contacts(0.1).is_empty());
+    }
+
+    #[test]
+    fn copes_with_pushes_of_no_length_or_radius() {
+        let mut fabric = Fabric::new(4);
+        for x in [-1.0, 1.0] {
+            let alpha = fabric.create_joint(x, 1.0, 0.0);
+            let omega = fabric.create_joint(x, 1.0, 0.0);
+            fabric.create_interval(alpha, omega, true, 1.0, 1.0, 1.0, 0.0);
+        }
+        assert!(fabric.detect_contacts(0.0).is_empty());
+        let hash = SpatialHash::new(0.0);
+        assert_ne!(hash.cell(Point3::new(-1.0, 1.0, 0.0)), hash.cell(Point3::new(-2.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn repels_bars_passing_through() {
+        let mut world = World::new();
+        world.set_collision_mode(CollisionMode::Repel);
+        world.set_bar_radius(0.05);
+        let mut fabric = crossed_pushes(0.0);
+        fabric.stage = Stage::Pretenst;
+        fabric.accumulate_forces(&world, 1.0);
+        assert_eq!(fabric.contacts().len(), 1);
+        let normal = fabric.contacts()[0].normal;
+        let upward: Real = fabric.joints[2..].iter().map(|joint| joint.force.dot(&normal)).sum();
+        let downward: Real = fabric.joints[..2].iter().map(|joint| joint.force.dot(&normal)).sum();
+        assert!(upward > 0.0);
+        assert!((upward + downward).abs() < upward * 1e-5);
+        world.set_collision_mode(CollisionMode::Report);
+        fabric.iterate(&world);
+        assert_eq!(fabric.contacts().len(), 1);
+        let gap = fabric.joints[2].location.y - fabric.joints[0].location.y;
+        assert!(gap.abs() < 1e-5, "reporting moved the bars {}", gap);
+    }
+}
@@ -3,9 +3,23 @@
  * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
  */
 
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+/// The scalar of the simulation: `f32` as in the browser, or `f64` with the `f64` feature for
+/// long runs and analysis that need the precision.
+#[cfg(not(feature = "f64"))]
+pub type Real = f32;
+#[cfg(feature = "f64")]
+pub type Real = f64;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts as real_consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts as real_consts;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd)]
 pub enum Stage {
@@ -16,7 +30,8 @@ pub enum Stage {
     Pretenst,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SurfaceCharacter {
@@ -25,7 +40,50 @@ pub enum SurfaceCharacter {
     Bouncy,
 }
 
-#[wasm_bindgen]
+/// How each tick moves the joints from the forces on them.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Integrator {
+    /// Velocity first and then location, the original step and the cheapest.
+    SemiImplicitEuler,
+    /// Second order with two force evaluations, keeps energy well in undamped motion.
+    VelocityVerlet,
+    /// Classic fourth order Runge-Kutta with four force evaluations.
+    RungeKutta4,
+    /// Backward Euler linearized around the current shape, stable at any stiffness but damping.
+    ImplicitEuler,
+}
+
+/// What happens to a pretenst interval of some material loaded past what it can carry.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Intervals carry any load.
+    Off,
+    /// Failed intervals disappear from the fabric.
+    Remove,
+    /// Failed intervals stay, with a fraction of their stiffness.
+    Degrade,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionMode {
+    /// Pushes pass through each other unnoticed.
+    Off,
+    /// Clashing pushes are listed in the fabric's contacts but pass through each other.
+    Report,
+    /// Clashing pushes are listed and pushed apart.
+    Repel,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum WorldFeature {
@@ -44,41 +102,66 @@ pub enum WorldFeature {
     PretensingCountdown,
 }
 
-#[wasm_bindgen]
-pub fn default_world_feature(fabric_feature: WorldFeature) -> f32 {
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn default_world_feature(fabric_feature: WorldFeature) -> Real {
     match fabric_feature {
-        WorldFeature::Gravity => 2e-7_f32,
-        WorldFeature::Antigravity => 0.001_f32,
-        WorldFeature::ShapingDrag => 0.0005_f32,
-        WorldFeature::Drag => 0.0001_f32,
-        WorldFeature::ShapingPretenstFactor => 0.3_f32,
-        WorldFeature::PretenstFactor => 0.03_f32,
-        WorldFeature::ShapingStiffnessFactor => 0.0005_f32,
-        WorldFeature::StiffnessFactor => 0.01_f32,
-        WorldFeature::IterationsPerFrame => 50_f32,
-        WorldFeature::IntervalCountdown => 2000_f32,
-        WorldFeature::PretensingCountdown => 10000_f32,
-        WorldFeature::VisualStrain => 1_f32,
-        WorldFeature::PushOverPull => 3_f32,
+        WorldFeature::Gravity => 2e-7,
+        WorldFeature::Antigravity => 0.001,
+        WorldFeature::ShapingDrag => 0.0005,
+        WorldFeature::Drag => 0.0001,
+        WorldFeature::ShapingPretenstFactor => 0.3,
+        WorldFeature::PretenstFactor => 0.03,
+        WorldFeature::ShapingStiffnessFactor => 0.0005,
+        WorldFeature::StiffnessFactor => 0.01,
+        WorldFeature::IterationsPerFrame => 50.0,
+        WorldFeature::IntervalCountdown => 2000.0,
+        WorldFeature::PretensingCountdown => 10000.0,
+        WorldFeature::VisualStrain => 1.0,
+        WorldFeature::PushOverPull => 3.0,
     }
 }
 
-#[wasm_bindgen]
-extern "C" {
-    // Use `js_namespace` here to bind `console.log(..)` instead of just
-    // `log(..)`
-    #[wasm_bindgen(js_namespace = console)]
-    pub fn log(s: &str);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
 
-    // The `console.log` is quite polymorphic, so we can bind it with multiple
-    // signatures. Note that we need to use `js_name` to ensure we always call
-    // `log` in JS.
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    pub fn log_f32(s: &str, a: f32);
-
-    // The `console.log` is quite polymorphic, so we can bind it with multiple
-    // signatures. Note that we need to use `js_name` to ensure we always call
-    // `log` in JS.
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    pub fn log_u32(s: &str, a: u32);
+    /// The browser sees these enums as their numbers. The client compares stages by number and
+    /// keeps the current world feature in local storage by number, so new variants only go last.
+    #[test]
+    fn keeps_the_client_numbering() {
+        let stages = [Stage::Growing, Stage::Shaping, Stage::Slack, Stage::Pretensing, Stage::Pretenst];
+        assert_eq!(stages.map(|stage| stage as u8), [0, 1, 2, 3, 4]);
+        let surfaces = [SurfaceCharacter::Frozen, SurfaceCharacter::Sticky, SurfaceCharacter::Bouncy];
+        assert_eq!(surfaces.map(|surface| surface as u8), [0, 1, 2]);
+        let features = [
+            WorldFeature::VisualStrain,
+            WorldFeature::IterationsPerFrame,
+            WorldFeature::Gravity,
+            WorldFeature::PretenstFactor,
+            WorldFeature::StiffnessFactor,
+            WorldFeature::PushOverPull,
+            WorldFeature::Drag,
+            WorldFeature::ShapingPretenstFactor,
+            WorldFeature::ShapingDrag,
+            WorldFeature::ShapingStiffnessFactor,
+            WorldFeature::Antigravity,
+            WorldFeature::IntervalCountdown,
+            WorldFeature::PretensingCountdown,
+        ];
+        assert_eq!(features.map(|feature| feature as u8), [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let integrators = [
+            Integrator::SemiImplicitEuler,
+            Integrator::VelocityVerlet,
+            Integrator::RungeKutta4,
+            Integrator::ImplicitEuler,
+        ];
+        assert_eq!(integrators.map(|integrator| integrator as u8), [0, 1, 2, 3]);
+        let failures = [FailureMode::Off, FailureMode::Remove, FailureMode::Degrade];
+        assert_eq!(failures.map(|failure| failure as u8), [0, 1, 2]);
+        let collisions = [CollisionMode::Off, CollisionMode::Report, CollisionMode::Repel];
+        assert_eq!(collisions.map(|collision| collision as u8), [0, 1, 2]);
+        let materials = [Material::SteelRod, Material::AluminiumTube, Material::Bamboo, Material::DyneemaLine];
+        assert_eq!(materials.map(|material| material as u8), [0, 1, 2, 3]);
+    }
 }
@@ -0,0 +1,155 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use nalgebra::*;
+
+use crate::constants::Real;
+use crate::fabric::Fabric;
+use crate::world::World;
+
+/// How the fabric was doing at the end of a frame, in model units.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    pub kinetic_energy: Real,
+    /// The energy stored in stretched or squeezed intervals, pushes and pulls apart.
+    pub push_energy: Real,
+    pub pull_energy: Real,
+    /// The height of every joint times its weight, zero at the surface.
+    pub gravitational_energy: Real,
+    pub max_speed: Real,
+    /// The norm of the forces left unbalanced on the joints in the last tick, leaving out what
+    /// the supports and the surface carry.
+    pub residual_force: Real,
+    /// The largest interval tension, which the residual force is measured against.
+    pub max_tension: Real,
+    pub momentum: Vector3<Real>,
+}
+
+impl Diagnostics {
+    pub fn total_energy(&self) -> Real {
+        self.kinetic_energy + self.push_energy + self.pull_energy + self.gravitational_energy
+    }
+
+    pub fn meets(&self, criterion: &SettledCriterion) -> bool {
+        self.max_speed <= criterion.max_speed && self.residual_force <= criterion.max_residual * self.max_tension
+    }
+}
+
+/// When a fabric counts as being at rest: no joint faster than `max_speed`, a residual force of
+/// at most `max_residual` times the largest tension, and both for `frames` frames in a row.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SettledCriterion {
+    pub max_speed: Real,
+    pub max_residual: Real,
+    pub frames: u32,
+}
+
+pub const DEFAULT_SETTLED_CRITERION: SettledCriterion = SettledCriterion {
+    max_speed: 1e-6,
+    max_residual: 1e-3,
+    frames: 10,
+};
+
+impl Fabric {
+    /// The diagnostics of the last frame.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Whether the world's settled criterion has held for enough frames in a row, so the
+    /// fabric is really at rest rather than just done with its countdowns.
+    pub fn is_settled(&self, world: &World) -> bool {
+        self.settled_frames >= world.settled_criterion.frames
+    }
+
+    pub(crate) fn diagnose(&mut self, world: &World) {
+        let pretensing_nuance = world.pretensing_nuance(self);
+        let mut diagnostics = Diagnostics::default();
+        let gravity = self.motion(world).map_or(0.0, |(gravity, _)| gravity);
+        let mut residual_squared = 0.0;
+        for joint in &self.joints {
+            let mass = joint.interval_mass;
+            diagnostics.kinetic_energy += mass * joint.velocity.magnitude_squared() / 2.0;
+            diagnostics.gravitational_energy += mass * world.gravity * joint.location.y;
+            diagnostics.max_speed = diagnostics.max_speed.max(joint.velocity.magnitude());
+            diagnostics.momentum += joint.velocity * mass;
+            let mut force = joint.force;
+            force.y -= gravity * mass;
+            if joint.location.y <= 0.0 && force.y < 0.0 {
+                force.y = 0.0; // resting on the surface
+            }
+            residual_squared += joint.free(force).magnitude_squared();
+        }
+        diagnostics.residual_force = residual_squared.sqrt();
+        for interval in &self.intervals {
+            let energy = interval.elastic_energy(world, self.stage, pretensing_nuance);
+            if interval.push {
+                diagnostics.push_energy += energy;
+            } else {
+                diagnostics.pull_energy += energy;
+            }
+            diagnostics.max_tension = diagnostics.max_tension.max(interval.tension(world, self.stage).abs());
+        }
+        if diagnostics.meets(&world.settled_criterion) {
+            self.settled_frames += 1;
+        } else {
+            self.settled_frames = 0;
+        }
+        self.diagnostics = diagnostics;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+
+    #[test]
+    fn ringing_keeps_its_energy_and_momentum() {
+        let mut world = World::new();
+        world.set_float_value(WorldFeature::Drag, 0.0);
+        world.set_float_value(WorldFeature::Gravity, 0.0);
+        world.set_push_and_pull(true);
+        world.set_time_step(0.05);
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 1.0, 0.0);
+        let omega = fabric.create_joint(1.01, 1.0, 0.0);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.stage = Stage::Pretenst;
+        fabric.iterate(&world);
+        let first = fabric.diagnostics().clone();
+        assert!(first.pull_energy > 0.0 && first.push_energy == 0.0);
+        for _ in 0..10 {
+            fabric.iterate(&world);
+            let diagnostics = fabric.diagnostics();
+            assert!((diagnostics.total_energy() - first.total_energy()).abs() < first.total_energy() * 0.05);
+            assert!(diagnostics.momentum.magnitude() < 1e-9);
+        }
+        assert!(!fabric.is_settled(&world));
+    }
+
+    #[test]
+    fn damped_fabric_settles() {
+        let mut world = World::new();
+        world.set_float_value(WorldFeature::Drag, 0.05);
+        world.set_push_and_pull(true);
+        // a light interval stops where the rounding of its location is already a good part of its weight
+        world.set_settled_criterion(SettledCriterion { max_speed: 1e-6, max_residual: 0.1, frames: 3 });
+        let mut fabric = Fabric::new(2);
+        let top = fabric.create_joint(0.0, 5.0, 0.0);
+        let bottom = fabric.create_joint(0.0, 4.0, 0.0);
+        fabric.create_interval(top, bottom, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.pin_joint(top, [true; 3]);
+        fabric.stage = Stage::Pretenst;
+        let frames = (0..200).position(|_| {
+            fabric.iterate(&world);
+            fabric.is_settled(&world)
+        });
+        assert!(frames.is_some(), "{:?}", fabric.diagnostics());
+        assert!(fabric.diagnostics().gravitational_energy > 0.0);
+    }
+}
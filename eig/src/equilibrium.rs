@@ -0,0 +1,484 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::fmt::{Display, Formatter};
+
+use nalgebra::*;
+
+use crate::constants::*;
+use crate::fabric::Fabric;
+use crate::world::World;
+
+/// Added to the diagonal, relative to its largest entry, so that rigid motion does not make the matrix singular.
+const REGULARIZATION: f64 = 1e-9;
+const MIN_STEP_FRACTION: Real = 1.0 / 1024.0;
+/// Each Newton step is solved until the forces left over are this fraction of those it started with.
+const CONJUGATE_GRADIENT_TOLERANCE: f64 = 1e-8;
+/// Rotations about a line of joints do not move them, so they fall below this relative singular value.
+const RIGID_TOLERANCE: f64 = 1e-9;
+/// Smaller tensions in a state of self-stress, relative to the largest, count as zero.
+const PROPER_TENSION: Real = 1e-6;
+
+/// A fabric found at rest, with the norm of the joint forces which remain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Equilibrium {
+    pub iterations: usize,
+    pub residual: Real,
+}
+
+/// What the equilibrium matrix says about a fabric's topology and geometry, regardless of rest lengths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EquilibriumAnalysis {
+    /// The rank of the equilibrium matrix, with a row per joint coordinate and a column per interval.
+    pub rank: usize,
+    pub self_stresses: Vec<SelfStress>,
+    /// Independent infinitesimal mechanisms as a velocity per joint, with rigid motion taken out.
+    pub mechanisms: Vec<Vec<Vector3<Real>>>,
+}
+
+impl EquilibriumAnalysis {
+    /// Whether some state of self-stress pulls every pull and pushes every push. This is exact
+    /// for the usual single state, but with several states only each basis state is tried.
+    pub fn is_prestressable(&self) -> bool {
+        self.self_stresses.iter().any(|self_stress| self_stress.proper)
+    }
+}
+
+/// Tensions, one per interval, which balance at every joint with no load at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfStress {
+    /// Scaled so the largest is one in size, and signed so that the pulls are mostly in tension.
+    pub tensions: Vec<Real>,
+    /// Every pull in tension and every push in compression.
+    pub proper: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EquilibriumError {
+    Slack,
+    /// The stiffness gives no way down along the remaining forces, as when a mechanism is loaded.
+    Singular { iteration: usize },
+    NotConverged { iterations: usize, residual: Real },
+}
+
+impl Display for EquilibriumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EquilibriumError::Slack =>
+                write!(f, "a slack fabric has no stiffness"),
+            EquilibriumError::Singular { iteration } =>
+                write!(f, "no stiffness against the forces at iteration {iteration}"),
+            EquilibriumError::NotConverged { iterations, residual } =>
+                write!(f, "no equilibrium after {iterations} iterations, residual force {residual:e}"),
+        }
+    }
+}
+
+impl std::error::Error for EquilibriumError {}
+
+impl Fabric {
+    /// The norm of the forces the intervals and loads leave on the joints, zero in equilibrium.
+    /// What pinned joints feel along their pinned axes is taken by the supports.
+    pub fn residual_force(&mut self, world: &World) -> Real {
+        let pretensing_nuance = world.pretensing_nuance(self);
+        self.accumulate_forces(world, pretensing_nuance);
+        self.joints
+            .iter()
+            .map(|joint| joint.free(joint.force).magnitude_squared())
+            .sum::<Real>()
+            .sqrt()
+    }
+
+    /// Move the joints to where the intervals balance, by Newton-Raphson on the tangent stiffness
+    /// with a backtracking line search. Gravity and the surface are left out, so this is the
+    /// fabric's self-equilibrium at the rest lengths of its current stage, under any loads and
+    /// with pinned joints held where they are. Each step is solved by conjugate gradients, one
+    /// interval at a time, so the matrix is never assembled and large fabrics only cost time.
+    ///
+    /// Converged means a residual force of at most `tolerance` times the largest interval tension.
+    /// Only steps that lower the residual are taken, so when it fails to converge, either running
+    /// out of iterations or finding no step that helps, the joints stay where it was lowest.
+    pub fn solve_equilibrium(
+        &mut self,
+        world: &World,
+        max_iterations: usize,
+        tolerance: Real,
+    ) -> Result<Equilibrium, EquilibriumError> {
+        if self.stage == Stage::Slack {
+            return Err(EquilibriumError::Slack);
+        }
+        let pretensing_nuance = world.pretensing_nuance(self);
+        let mut residual = self.residual_force(world);
+        for iteration in 0..max_iterations {
+            if residual <= tolerance * self.max_tension(world) {
+                self.come_to_rest();
+                return Ok(Equilibrium { iterations: iteration, residual });
+            }
+            let free = self.free_coordinates();
+            let forces = DVector::from_iterator(
+                free.len(),
+                free.iter().map(|&coordinate| self.joints[coordinate / 3].force[coordinate % 3] as f64),
+            );
+            let blocks = self.stiffness_blocks(world, pretensing_nuance);
+            let regularization = self.stiffness_diagonal(&blocks).amax() * REGULARIZATION;
+            let stiffness = |vector: &DVector<f64>| -> DVector<f64> {
+                let mut spread = DVector::<f64>::zeros(self.joints.len() * 3);
+                for (&coordinate, &value) in free.iter().zip(vector.iter()) {
+                    spread[coordinate] = value;
+                }
+                let product = self.tangent_product(&blocks, &spread);
+                DVector::from_iterator(free.len(), free.iter().map(|&coordinate| product[coordinate])) + vector * regularization
+            };
+            let free_step = conjugate_gradient(stiffness, &forces, free.len())
+                .ok_or(EquilibriumError::Singular { iteration })?;
+            let mut step = DVector::<f64>::zeros(self.joints.len() * 3);
+            for (&coordinate, &delta) in free.iter().zip(free_step.iter()) {
+                step[coordinate] = delta;
+            }
+            let start: Vec<Point3<Real>> = self.joints.iter().map(|joint| joint.location).collect();
+            let mut fraction = 1.0;
+            loop {
+                for (index, joint) in self.joints.iter_mut().enumerate() {
+                    let delta = Vector3::new(step[index * 3], step[index * 3 + 1], step[index * 3 + 2]);
+                    joint.location = start[index] + delta.cast::<Real>() * fraction;
+                }
+                let trial = self.residual_force(world);
+                if trial < residual {
+                    residual = trial;
+                    break;
+                }
+                if fraction <= MIN_STEP_FRACTION {
+                    for (joint, location) in self.joints.iter_mut().zip(&start) {
+                        joint.location = *location;
+                    }
+                    self.residual_force(world);
+                    return Err(EquilibriumError::NotConverged { iterations: iteration + 1, residual });
+                }
+                fraction /= 2.0;
+            }
+        }
+        if residual <= tolerance * self.max_tension(world) {
+            self.come_to_rest();
+            return Ok(Equilibrium { iterations: max_iterations, residual });
+        }
+        Err(EquilibriumError::NotConverged { iterations: max_iterations, residual })
+    }
+
+    /// The derivative of the joint forces with respect to the joint locations, negated so that
+    /// a stable fabric gives a positive semi-definite matrix, singular in rigid motion. Strains and
+    /// unit vectors are those of the last force evaluation. It is dense, for finding the modes of
+    /// fabrics of some hundreds of joints, while `solve_equilibrium` only multiplies by it.
+    pub(crate) fn tangent_stiffness(&self, world: &World, pretensing_nuance: Real) -> DMatrix<f64> {
+        let size = self.joints.len() * 3;
+        let mut matrix = DMatrix::<f64>::zeros(size, size);
+        for (interval, block) in self.intervals.iter().zip(self.stiffness_blocks(world, pretensing_nuance)) {
+            let (alpha, omega) = (interval.alpha_index * 3, interval.omega_index * 3);
+            let blocks = [(alpha, alpha, 1_f64), (omega, omega, 1_f64), (alpha, omega, -1_f64), (omega, alpha, -1_f64)];
+            for (row, column, sign) in IntoIterator::into_iter(blocks) {
+                let mut slice = matrix.fixed_slice_mut::<3, 3>(row, column);
+                slice += block * sign;
+            }
+        }
+        matrix
+    }
+
+    /// What each interval adds to the tangent stiffness at either end: its axial stiffness along
+    /// its unit vector and its tension over length across it, which is negative for compressed pushes.
+    fn stiffness_blocks(&self, world: &World, pretensing_nuance: Real) -> Vec<Matrix3<f64>> {
+        self.intervals
+            .iter()
+            .map(|interval| {
+                let length = interval.calculate_current_length(&self.joints) as f64;
+                let unit: Vector3<f64> = interval.unit.cast();
+                let axial = interval.axial_stiffness(world, self.stage, pretensing_nuance) as f64;
+                let geometric = interval.tension(world, self.stage) as f64 / length;
+                let outer = unit * unit.transpose();
+                outer * axial + (Matrix3::identity() - outer) * geometric
+            })
+            .collect()
+    }
+
+    /// The tangent stiffness times a vector of joint coordinates, without assembling the matrix.
+    fn tangent_product(&self, blocks: &[Matrix3<f64>], vector: &DVector<f64>) -> DVector<f64> {
+        let mut product = DVector::<f64>::zeros(vector.len());
+        for (interval, block) in self.intervals.iter().zip(blocks) {
+            let (alpha, omega) = (interval.alpha_index * 3, interval.omega_index * 3);
+            let stretch = vector.fixed_rows::<3>(omega) - vector.fixed_rows::<3>(alpha);
+            let pull = block * stretch;
+            let mut at_alpha = product.fixed_rows_mut::<3>(alpha);
+            at_alpha -= &pull;
+            let mut at_omega = product.fixed_rows_mut::<3>(omega);
+            at_omega += &pull;
+        }
+        product
+    }
+
+    /// The diagonal of the tangent stiffness.
+    fn stiffness_diagonal(&self, blocks: &[Matrix3<f64>]) -> DVector<f64> {
+        let mut diagonal = DVector::<f64>::zeros(self.joints.len() * 3);
+        for (interval, block) in self.intervals.iter().zip(blocks) {
+            for end in [interval.alpha_index * 3, interval.omega_index * 3] {
+                let mut at_end = diagonal.fixed_rows_mut::<3>(end);
+                at_end += block.diagonal();
+            }
+        }
+        diagonal
+    }
+
+    /// A row per joint coordinate and a column per interval, holding the unit vector from alpha to
+    /// omega at alpha and its opposite at omega, so that multiplying by tensions gives joint forces.
+    pub fn equilibrium_matrix(&self) -> DMatrix<f64> {
+        let mut matrix = DMatrix::<f64>::zeros(self.joints.len() * 3, self.intervals.len());
+        for (column, interval) in self.intervals.iter().enumerate() {
+            let alpha = interval.alpha(&self.joints).location.cast::<f64>();
+            let omega = interval.omega(&self.joints).location.cast::<f64>();
+            let unit = (omega - alpha).try_normalize(0_f64).unwrap_or_else(zero);
+            for axis in 0..3 {
+                matrix[(interval.alpha_index * 3 + axis, column)] = unit[axis];
+                matrix[(interval.omega_index * 3 + axis, column)] = -unit[axis];
+            }
+        }
+        matrix
+    }
+
+    /// Count and find the states of self-stress and the infinitesimal mechanisms from the singular
+    /// value decomposition of the equilibrium matrix. Singular values below `tolerance` times the
+    /// largest count as zero, and since a simulated shape is only close to equilibrium a tolerance
+    /// around the relative residual force makes sense.
+    pub fn analyze_equilibrium(&self, tolerance: f64) -> EquilibriumAnalysis {
+        let matrix = self.equilibrium_matrix();
+        let (rank, stress_space) = null_space(matrix.clone(), tolerance);
+        let (_, motion_space) = null_space(matrix.transpose(), tolerance);
+        let self_stresses = stress_space
+            .iter()
+            .map(|tensions| self.self_stress(tensions))
+            .collect();
+        let mechanisms = self
+            .without_rigid_motion(motion_space)
+            .iter()
+            .map(|motion| {
+                let largest = motion.amax();
+                (0..self.joints.len())
+                    .map(|joint| Vector3::new(motion[joint * 3], motion[joint * 3 + 1], motion[joint * 3 + 2]))
+                    .map(|velocity| (velocity / largest).cast::<Real>())
+                    .collect()
+            })
+            .collect();
+        EquilibriumAnalysis { rank, self_stresses, mechanisms }
+    }
+
+    fn self_stress(&self, tensions: &DVector<f64>) -> SelfStress {
+        let pull_sum: f64 = self.intervals
+            .iter()
+            .zip(tensions.iter())
+            .filter(|(interval, _)| !interval.push)
+            .map(|(_, tension)| tension)
+            .sum();
+        let scale = if pull_sum < 0_f64 { -tensions.amax() } else { tensions.amax() };
+        let tensions: Vec<Real> = tensions.iter().map(|tension| (tension / scale) as Real).collect();
+        let proper = self.intervals
+            .iter()
+            .zip(&tensions)
+            .all(|(interval, &tension)| {
+                if interval.push { tension < -PROPER_TENSION } else { tension > PROPER_TENSION }
+            });
+        SelfStress { tensions, proper }
+    }
+
+    /// An orthonormal basis for what is left of the motions once translation and rotation are removed.
+    fn without_rigid_motion(&self, motions: Vec<DVector<f64>>) -> Vec<DVector<f64>> {
+        let size = self.joints.len() * 3;
+        if motions.is_empty() || size == 0 {
+            return Vec::new();
+        }
+        let center = self.joints
+            .iter()
+            .map(|joint| joint.location.coords.cast::<f64>())
+            .sum::<Vector3<f64>>() / self.joints.len() as f64;
+        let mut rigid = DMatrix::<f64>::zeros(size, 6);
+        for (index, joint) in self.joints.iter().enumerate() {
+            let arm = joint.location.coords.cast::<f64>() - center;
+            for axis in 0..3 {
+                let direction = Vector3::ith(axis, 1_f64);
+                let spin = direction.cross(&arm);
+                for coordinate in 0..3 {
+                    rigid[(index * 3 + coordinate, axis)] = direction[coordinate];
+                    rigid[(index * 3 + coordinate, 3 + axis)] = spin[coordinate];
+                }
+            }
+        }
+        let rigid_basis = column_space(rigid, RIGID_TOLERANCE);
+        let projected = DMatrix::from_columns(&motions.iter()
+            .map(|motion| motion - &rigid_basis * (rigid_basis.transpose() * motion))
+            .collect::<Vec<_>>());
+        let remaining = column_space(projected, 0.5_f64);
+        remaining.column_iter().map(|column| column.into_owned()).collect()
+    }
+
+    /// The joint coordinates, three per joint, that are not pinned.
+    pub(crate) fn free_coordinates(&self) -> Vec<usize> {
+        self.joints
+            .iter()
+            .enumerate()
+            .flat_map(|(index, joint)| (0..3).filter(move |&axis| !joint.pinned[axis]).map(move |axis| index * 3 + axis))
+            .collect()
+    }
+
+    fn max_tension(&self, world: &World) -> Real {
+        self.intervals
+            .iter()
+            .map(|interval| interval.tension(world, self.stage).abs())
+            .fold(0.0, Real::max)
+    }
+
+    fn come_to_rest(&mut self) {
+        for joint in &mut self.joints {
+            joint.velocity.fill(0.0);
+        }
+    }
+}
+
+/// Solve `matrix x = b` for a symmetric positive definite matrix known only by its products, or
+/// give up if a direction turns up along which it is not positive at the very start.
+fn conjugate_gradient(matrix: impl Fn(&DVector<f64>) -> DVector<f64>, b: &DVector<f64>, max_iterations: usize) -> Option<DVector<f64>> {
+    let mut solution = DVector::<f64>::zeros(b.len());
+    let mut residual = b.clone();
+    let mut direction = b.clone();
+    let mut residual_squared = residual.norm_squared();
+    let tolerance = residual_squared * CONJUGATE_GRADIENT_TOLERANCE * CONJUGATE_GRADIENT_TOLERANCE;
+    for iteration in 0..max_iterations {
+        if residual_squared <= tolerance {
+            break;
+        }
+        let matrix_direction = matrix(&direction);
+        let curvature = direction.dot(&matrix_direction);
+        if curvature <= 0_f64 {
+            // what was found so far still lowers the energy, past that the matrix is no guide
+            if iteration == 0 {
+                return None;
+            }
+            break;
+        }
+        let step = residual_squared / curvature;
+        solution.axpy(step, &direction, 1_f64);
+        residual.axpy(-step, &matrix_direction, 1_f64);
+        let next_residual_squared = residual.norm_squared();
+        direction = &residual + &direction * (next_residual_squared / residual_squared);
+        residual_squared = next_residual_squared;
+    }
+    Some(solution)
+}
+
+/// The right singular vectors whose singular values are at most `tolerance` times the largest,
+/// along with the rank.
+fn null_space(matrix: DMatrix<f64>, tolerance: f64) -> (usize, Vec<DVector<f64>>) {
+    let columns = matrix.ncols();
+    if columns == 0 {
+        return (0, Vec::new());
+    }
+    // at least as many rows as columns, so that the decomposition has every right singular vector
+    let rows = matrix.nrows().max(columns);
+    let svd = matrix.resize(rows, columns, 0_f64).svd(false, true);
+    let threshold = svd.singular_values.max() * tolerance;
+    let v_t = svd.v_t.expect("asked for v_t");
+    let rank = svd.singular_values.iter().filter(|&&value| value > threshold).count();
+    let null = svd.singular_values
+        .iter()
+        .enumerate()
+        .filter(|(_, &value)| value <= threshold)
+        .map(|(index, _)| v_t.row(index).transpose())
+        .collect();
+    (rank, null)
+}
+
+/// An orthonormal basis for the columns, leaving out directions with relatively small singular values.
+fn column_space(matrix: DMatrix<f64>, tolerance: f64) -> DMatrix<f64> {
+    let svd = matrix.svd(true, false);
+    let threshold = svd.singular_values.max() * tolerance;
+    let u = svd.u.expect("asked for u");
+    let columns: Vec<DVector<f64>> = svd.singular_values
+        .iter()
+        .enumerate()
+        .filter(|(_, &value)| value > threshold)
+        .map(|(index, _)| u.column(index).into_owned())
+        .collect();
+    DMatrix::from_columns(&columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::Builder;
+    use crate::tenscript::parse;
+
+    fn growing_column() -> (Fabric, World) {
+        let plan = parse("(fabric (build (seed :left) (grow A+ 3)))").unwrap();
+        let mut world = World::from_plan(&plan).unwrap();
+        let mut fabric = Fabric::new(100);
+        let mut builder = Builder::new(plan, &world).unwrap();
+        for _ in 0..40 {
+            builder.iterate(&mut fabric, &mut world);
+        }
+        (fabric, world)
+    }
+
+    #[test]
+    fn balances_a_growing_column() {
+        let (mut fabric, world) = growing_column();
+        let before = fabric.residual_force(&world);
+        let equilibrium = fabric.solve_equilibrium(&world, 50, 1e-4).unwrap();
+        assert!(equilibrium.residual < before / 100.0, "{} from {}", equilibrium.residual, before);
+        assert_eq!(fabric.residual_force(&world), equilibrium.residual);
+    }
+
+    #[test]
+    fn stays_where_the_residual_was_lowest() {
+        let (mut fabric, world) = growing_column();
+        let before = fabric.residual_force(&world);
+        for max_iterations in [1, 100] {
+            let Err(EquilibriumError::NotConverged { residual, .. }) = fabric.solve_equilibrium(&world, max_iterations, 0.0) else {
+                panic!("converged to no residual at all");
+            };
+            assert!(residual < before, "{} from {}", residual, before);
+            assert_eq!(fabric.residual_force(&world), residual);
+        }
+    }
+
+    #[test]
+    fn multiplies_like_the_assembled_stiffness() {
+        let (mut fabric, world) = growing_column();
+        fabric.residual_force(&world);
+        let size = fabric.joints.len() * 3;
+        let vector = DVector::from_fn(size, |index, _| ((index * 7) % 11) as f64 - 5_f64);
+        let blocks = fabric.stiffness_blocks(&world, 0.0);
+        let assembled = fabric.tangent_stiffness(&world, 0.0);
+        let difference = fabric.tangent_product(&blocks, &vector) - &assembled * &vector;
+        assert!(difference.amax() <= assembled.amax() * 1e-12, "off by {}", difference.amax());
+        assert_eq!(fabric.stiffness_diagonal(&blocks), assembled.diagonal());
+    }
+
+    #[test]
+    fn braced_square_has_one_self_stress_and_one_mechanism() {
+        let mut fabric = Fabric::new(4);
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        for (x, z) in IntoIterator::into_iter(corners) {
+            fabric.create_joint(x, 1.0, z);
+        }
+        let sides = [(0, 1, false), (1, 2, false), (2, 3, false), (3, 0, false)];
+        let diagonals = [(0, 2, true), (1, 3, true)];
+        for (alpha, omega, push) in IntoIterator::into_iter(sides).chain(diagonals) {
+            fabric.create_interval(alpha, omega, push, 1.0, 1.0, 1.0, 0.0);
+        }
+        let analysis = fabric.analyze_equilibrium(1e-9);
+        assert_eq!(analysis.rank, 5);
+        assert_eq!(analysis.self_stresses.len(), 1);
+        assert!(analysis.is_prestressable());
+        let tensions = &analysis.self_stresses[0].tensions;
+        let ratio = tensions[4] / tensions[0];
+        assert!((ratio + 2.0.sqrt()).abs() < 1e-5, "diagonal over side {}", ratio);
+        assert_eq!(analysis.mechanisms.len(), 1);
+        assert!(analysis.mechanisms[0].iter().all(|velocity| velocity.x.abs() < 1e-5 && velocity.z.abs() < 1e-5));
+    }
+}
@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::fmt::Write;
+
+use crate::fabric::Fabric;
+
+/// Wavefront OBJ with a vertex per joint, push and pull intervals as separate
+/// groups of lines, and the faces as triangles.
+pub fn obj(fabric: &Fabric) -> String {
+    let mut out = String::new();
+    writeln!(out, "# {} joints, {} intervals, {} faces", fabric.joints.len(), fabric.intervals.len(), fabric.faces.len()).unwrap();
+    for joint in &fabric.joints {
+        let location = joint.location;
+        writeln!(out, "v {} {} {}", location.x, location.y, location.z).unwrap();
+    }
+    for (group, push) in [("pushes", true), ("pulls", false)] {
+        writeln!(out, "g {group}").unwrap();
+        for interval in fabric.intervals.iter().filter(|interval| interval.push == push) {
+            writeln!(out, "l {} {}", interval.alpha_index + 1, interval.omega_index + 1).unwrap();
+        }
+    }
+    if !fabric.faces.is_empty() {
+        writeln!(out, "g faces").unwrap();
+        for face in &fabric.faces {
+            let [a, b, c] = face.joints;
+            writeln!(out, "f {} {} {}", a + 1, b + 1, c + 1).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_obj() {
+        let mut fabric = Fabric::new(3);
+        let a = fabric.create_joint(0.0, 0.0, 0.0);
+        let b = fabric.create_joint(1.0, 0.0, 0.0);
+        let c = fabric.create_joint(0.0, 1.5, 0.0);
+        fabric.create_interval(a, b, true, 1.0, 1.0, 1.0, 0.0);
+        fabric.create_interval(b, c, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.create_interval(c, a, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.create_face(a, b, c);
+        let expected = "\
+# 3 joints, 3 intervals, 1 faces
+v 0 0 0
+v 1 0 0
+v 0 1.5 0
+g pushes
+l 1 2
+g pulls
+l 2 3
+l 3 1
+g faces
+f 1 2 3
+";
+        assert_eq!(obj(&fabric), expected);
+    }
+}
@@ -4,38 +4,65 @@
  */
 
 use nalgebra::*;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+use crate::collision::Contact;
 use crate::constants::*;
+use crate::diagnostics::Diagnostics;
 use crate::face::Face;
+use crate::failure::FailureEvent;
 use crate::interval::Interval;
 use crate::joint::Joint;
+use crate::kernel::Kernel;
+use crate::support::PointLoad;
 use crate::world::World;
 
-pub const DEFAULT_STRAIN_LIMITS: [f32; 4] = [0_f32, -1e9_f32, 1e9_f32, 0_f32];
+pub const DEFAULT_STRAIN_LIMITS: [Real; 4] = [0.0, -1e9, 1e9, 0.0];
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct Fabric {
     pub age: u32,
     pub(crate) stage: Stage,
     pub(crate) joints: Vec<Joint>,
     pub(crate) intervals: Vec<Interval>,
     pub(crate) faces: Vec<Face>,
-    pub(crate) pretensing_countdown: f32,
-    pub(crate) strain_limits: [f32; 4],
+    pub(crate) pretensing_countdown: Real,
+    pub(crate) strain_limits: [Real; 4],
+    pub(crate) metres_per_unit: Real,
+    pub(crate) failures: Vec<FailureEvent>,
+    /// Intervals removed by failing, in the order they went, until a builder catches up with them.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) removed_intervals: Vec<usize>,
+    pub(crate) loads: Vec<PointLoad>,
+    pub(crate) diagnostics: Diagnostics,
+    pub(crate) settled_frames: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) contacts: Vec<Contact>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) kernel: Kernel,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Fabric {
     pub fn new(joint_count: usize) -> Fabric {
         Fabric {
             age: 0,
             stage: Stage::Growing,
-            pretensing_countdown: 0_f32,
+            pretensing_countdown: 0.0,
             joints: Vec::with_capacity(joint_count),
             intervals: Vec::with_capacity(joint_count * 10),
             faces: Vec::with_capacity(joint_count),
             strain_limits: DEFAULT_STRAIN_LIMITS,
+            metres_per_unit: 1.0,
+            failures: Vec::new(),
+            removed_intervals: Vec::new(),
+            loads: Vec::new(),
+            diagnostics: Diagnostics::default(),
+            settled_frames: 0,
+            contacts: Vec::new(),
+            kernel: Kernel::default(),
         }
     }
 
@@ -44,9 +71,17 @@ impl Fabric {
         self.stage = Stage::Growing;
         self.joints.clear();
         self.intervals.clear();
+        self.kernel.clear();
         self.faces.clear();
+        self.failures.clear();
+        self.removed_intervals.clear();
+        self.loads.clear();
+        self.diagnostics = Diagnostics::default();
+        self.settled_frames = 0;
+        self.contacts.clear();
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn clone(&self) -> Fabric {
         Fabric {
             age: self.age,
@@ -56,6 +91,14 @@ impl Fabric {
             intervals: self.intervals.clone(),
             faces: self.faces.clone(),
             strain_limits: DEFAULT_STRAIN_LIMITS,
+            metres_per_unit: self.metres_per_unit,
+            failures: self.failures.clone(),
+            removed_intervals: self.removed_intervals.clone(),
+            loads: self.loads.clone(),
+            diagnostics: self.diagnostics.clone(),
+            settled_frames: self.settled_frames,
+            contacts: self.contacts.clone(),
+            kernel: Kernel::default(),
         }
     }
 
@@ -71,7 +114,7 @@ impl Fabric {
         self.faces.len() as u16
     }
 
-    pub fn create_joint(&mut self, x: f32, y: f32, z: f32) -> usize {
+    pub fn create_joint(&mut self, x: Real, y: Real, z: Real) -> usize {
         let index = self.joints.len();
         self.joints.push(Joint::new(x, y, z));
         index
@@ -82,22 +125,29 @@ impl Fabric {
         self.intervals
             .iter_mut()
             .for_each(|interval| interval.joint_removed(index));
+        self.kernel.joint_removed(index);
         self.faces
             .iter_mut()
             .for_each(|face| face.joint_removed(index));
+        self.loads.retain(|load| load.joint != index);
+        self.loads
+            .iter_mut()
+            .for_each(|load| load.joint_removed(index));
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_interval(
         &mut self,
         alpha_index: usize,
         omega_index: usize,
         push: bool,
-        length_0: f32,
-        length_1: f32,
-        stiffness: f32,
-        attack: f32,
+        length_0: Real,
+        length_1: Real,
+        stiffness: Real,
+        attack: Real,
     ) -> usize {
         let index = self.intervals.len();
+        self.kernel.interval_created(alpha_index, omega_index);
         self.intervals.push(Interval::new(
             alpha_index,
             omega_index,
@@ -112,6 +162,7 @@ impl Fabric {
 
     pub fn remove_interval(&mut self, index: usize) {
         self.intervals.remove(index);
+        self.kernel.interval_removed(index);
     }
 
     pub fn create_face(&mut self, joint0: usize, joint1: usize, joint2: usize) -> usize {
@@ -127,62 +178,62 @@ impl Fabric {
     pub fn twitch_interval(
         &mut self,
         interval_index: usize,
-        attack_countdown: f32,
-        decay_countdown: f32,
-        delta_size_nuance: f32,
+        attack_countdown: Real,
+        decay_countdown: Real,
+        delta_size_nuance: Real,
     ) {
         self.intervals[interval_index].twitch(attack_countdown, decay_countdown, delta_size_nuance)
     }
 
     pub fn centralize(&mut self) {
-        let mut midpoint: Vector3<f32> = zero();
+        let mut midpoint: Vector3<Real> = zero();
         for joint in self.joints.iter() {
             midpoint += &joint.location.coords;
         }
-        midpoint /= self.joints.len() as f32;
-        midpoint.y = 0_f32;
+        midpoint /= self.joints.len() as Real;
+        midpoint.y = 0.0;
         for joint in self.joints.iter_mut() {
             joint.location -= &midpoint;
         }
     }
 
-    pub fn set_altitude(&mut self, altitude: f32) {
-        match self
+    pub fn set_altitude(&mut self, altitude: Real) {
+        if self.joints.iter().any(|joint| joint.is_pinned()) {
+            return; // the supports decide
+        }
+        if let Some(low_y) = self
             .joints
             .iter()
             .filter(|joint| joint.is_connected())
             .map(|joint| joint.location.y)
-            .min_by(|a, b| a.partial_cmp(b).unwrap())
+            .reduce(Real::min)
         {
-            Some(low_y) => {
-                let up = altitude - low_y;
-                if up > 0_f32 {
-                    for joint in &mut self.joints {
-                        joint.location.y += up;
-                    }
+            let up = altitude - low_y;
+            if up > 0.0 {
+                for joint in &mut self.joints {
+                    joint.location.y += up;
                 }
             }
-            None => {}
         }
     }
 
-    pub fn multiply_rest_length(&mut self, index: usize, factor: f32, countdown: f32) {
+    pub fn multiply_rest_length(&mut self, index: usize, factor: Real, countdown: Real) {
         self.intervals[index].multiply_rest_length(factor, countdown);
     }
 
-    pub fn change_rest_length(&mut self, index: usize, rest_length: f32, countdown: f32) {
+    pub fn change_rest_length(&mut self, index: usize, rest_length: Real, countdown: Real) {
         self.intervals[index].change_rest_length(rest_length, countdown);
     }
 
-    pub fn apply_matrix4(&mut self, m: &[f32]) {
-        let matrix: Matrix4<f32> = Matrix4::from_vec(m.to_vec());
+    pub fn apply_matrix4(&mut self, m: &[Real]) {
+        let matrix: Matrix4<Real> = Matrix4::from_vec(m.to_vec());
         for joint in &mut self.joints {
             *joint.location = *matrix.transform_point(&joint.location);
             *joint.velocity = *matrix.transform_vector(&joint.velocity);
         }
     }
 
-    pub fn copy_stiffnesses(&mut self, new_stiffnesses: &mut [f32]) {
+    pub fn copy_stiffnesses(&mut self, new_stiffnesses: &mut [Real]) {
         for (index, interval) in &mut self.intervals.iter_mut().enumerate() {
             interval.stiffness = new_stiffnesses[index];
         }
@@ -199,8 +250,8 @@ impl Fabric {
             interval.length_1 = interval.length_0;
         }
         for joint in self.joints.iter_mut() {
-            joint.force.fill(0_f32);
-            joint.velocity.fill(0_f32);
+            joint.force.fill(0.0);
+            joint.velocity.fill(0.0);
         }
         self.set_stage(Stage::Slack)
     }
@@ -222,7 +273,7 @@ impl Fabric {
 
     fn calculate_strain_limits(&mut self) {
         self.strain_limits.copy_from_slice(&DEFAULT_STRAIN_LIMITS);
-        let margin = 1e-3_f32;
+        let margin = 1e-3;
         for interval in &self.intervals {
             let upper_strain = interval.strain + margin;
             let lower_strain = interval.strain - margin;
@@ -245,60 +296,53 @@ impl Fabric {
     }
 
     fn tick(&mut self, world: &World) {
-        for joint in &mut self.joints {
-            joint.reset();
-        }
         let pretensing_nuance = world.pretensing_nuance(self);
+        self.integrate(world, pretensing_nuance);
         for interval in &mut self.intervals {
-            interval.physics(world, &mut self.joints, self.stage, pretensing_nuance);
+            interval.advance(world.time_step);
+        }
+        if world.collision_mode == CollisionMode::Report {
+            self.contacts = self.detect_contacts(world.bar_radius);
+        }
+        if world.failure_mode != FailureMode::Off && self.stage == Stage::Pretenst {
+            self.fail_overloaded(world.failure_mode);
         }
         match self.stage {
-            Stage::Growing | Stage::Shaping | Stage::Pretensing => {
-                for joint in &mut self.joints {
-                    joint.velocity_physics(world, 0_f32, world.shaping_drag);
-                }
-                self.set_altitude(1_f32)
-            }
+            Stage::Growing | Stage::Shaping | Stage::Pretensing => self.set_altitude(1.0),
             Stage::Slack => {
-                if world.gravity != 0_f32 {
-                    self.set_altitude(1_f32)
+                if world.gravity != 0.0 {
+                    self.set_altitude(1.0)
                 }
             }
-            Stage::Pretenst => {
-                for joint in &mut self.joints {
-                    joint.velocity_physics(world, world.gravity, world.drag)
-                }
-            }
-        }
-        for joint in &mut self.joints {
-            joint.location_physics();
+            Stage::Pretenst => {}
         }
+        self.age += 1;
     }
 
     pub fn iterate(&mut self, world: &World) -> bool {
         for _tick in 0..(world.iterations_per_frame as usize) {
-            self.tick(&world);
+            self.tick(world);
         }
         self.calculate_strain_limits();
         for interval in self.intervals.iter_mut() {
             interval.strain_nuance = interval.calculate_strain_nuance(&self.strain_limits);
         }
-        self.age += world.iterations_per_frame as u32;
+        self.diagnose(world);
         let interval_busy_max = self
             .intervals
             .iter()
             .map(|i| i.length_nuance)
-            .fold(0_f32, f32::max);
-        if interval_busy_max > 0_f32 {
+            .fold(0.0, Real::max);
+        if interval_busy_max > 0.0 {
             return true;
         }
-        let pretensing_countdown: f32 = self.pretensing_countdown - world.iterations_per_frame;
-        self.pretensing_countdown = if pretensing_countdown < 0_f32 {
-            0_f32
+        let pretensing_countdown: Real = self.pretensing_countdown - world.iterations_per_frame * world.time_step;
+        self.pretensing_countdown = if pretensing_countdown < 0.0 {
+            0.0
         } else {
             pretensing_countdown
         };
-        self.pretensing_countdown > 0_f32
+        self.pretensing_countdown > 0.0
     }
 
     pub fn get_stage(&self) -> Stage {
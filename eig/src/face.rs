@@ -4,12 +4,14 @@
  */
 use nalgebra::*;
 
+use crate::constants::Real;
 use crate::joint::Joint;
-use crate::view::View;
+use crate::view::{render_float, View};
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Copy)]
 pub struct Face {
-    joints: [usize; 3],
+    pub(crate) joints: [usize; 3],
 }
 
 impl Face {
@@ -22,26 +24,26 @@ impl Face {
     pub fn joint_removed(&mut self, index: usize) {
         self.joints.iter_mut().for_each(|joint_index| {
             if *joint_index > index {
-                *joint_index = *joint_index - 1
+                *joint_index -= 1
             }
         })
     }
 
-    pub fn _joint<'a>(&self, joints: &'a Vec<Joint>, index: usize) -> &'a Joint {
+    pub fn _joint<'a>(&self, joints: &'a [Joint], index: usize) -> &'a Joint {
         &joints[self.joints[index]]
     }
 
-    pub fn _joint_mut<'a>(&self, joints: &'a mut Vec<Joint>, index: usize) -> &'a mut Joint {
+    pub fn _joint_mut<'a>(&self, joints: &'a mut [Joint], index: usize) -> &'a mut Joint {
         &mut joints[self.joints[index]]
     }
 
-    pub fn midpoint(&self, joints: &Vec<Joint>) -> Vector3<f32> {
-        (&joints[self.joints[0]].location.coords +
-            &joints[self.joints[1]].location.coords +
-            &joints[self.joints[2]].location.coords) / 3.0
+    pub fn midpoint(&self, joints: &[Joint]) -> Vector3<Real> {
+        (joints[self.joints[0]].location.coords +
+            joints[self.joints[1]].location.coords +
+            joints[self.joints[2]].location.coords) / 3.0
     }
 
-    pub fn normal(&self, joints: &Vec<Joint>) -> Vector3<f32> {
+    pub fn normal(&self, joints: &[Joint]) -> Vector3<Real> {
         let location0 = &joints[self.joints[0]].location;
         let location1 = &joints[self.joints[1]].location;
         let location2 = &joints[self.joints[2]].location;
@@ -50,20 +52,20 @@ impl Face {
         aa.cross(&bb).normalize()
     }
 
-    pub fn project_features(&self, joints: &Vec<Joint>, view: &mut View) {
+    pub fn project_features(&self, joints: &[Joint], view: &mut View) {
         let midpoint = self.midpoint(joints);
-        view.face_midpoints.push(midpoint.x);
-        view.face_midpoints.push(midpoint.y);
-        view.face_midpoints.push(midpoint.z);
+        view.face_midpoints.push(render_float(midpoint.x));
+        view.face_midpoints.push(render_float(midpoint.y));
+        view.face_midpoints.push(render_float(midpoint.z));
         let normal = self.normal(joints);
         for index in 0..3 {
             let location = &joints[self.joints[index]].location;
-            view.face_vertex_locations.push(location.x);
-            view.face_vertex_locations.push(location.y);
-            view.face_vertex_locations.push(location.z);
-            view.face_normals.push(normal.x);
-            view.face_normals.push(normal.y);
-            view.face_normals.push(normal.z);
+            view.face_vertex_locations.push(render_float(location.x));
+            view.face_vertex_locations.push(render_float(location.y));
+            view.face_vertex_locations.push(render_float(location.z));
+            view.face_normals.push(render_float(normal.x));
+            view.face_normals.push(render_float(normal.y));
+            view.face_normals.push(render_float(normal.z));
         }
     }
 }
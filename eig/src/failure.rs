@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use crate::constants::*;
+use crate::fabric::Fabric;
+
+/// The fraction of its stiffness that a degraded interval keeps.
+const DEGRADED_STIFFNESS: Real = 0.1;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    Buckling,
+    Rupture,
+}
+
+/// An interval giving way, with the load it felt and the load it could take, in newtons.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailureEvent {
+    pub age: u32,
+    /// The index of the interval when it failed, since removal shifts the ones after it.
+    pub interval: usize,
+    pub alpha_index: usize,
+    pub omega_index: usize,
+    pub kind: FailureKind,
+    pub newtons: Real,
+    pub limit: Real,
+}
+
+impl Fabric {
+    /// Every interval that has failed so far, oldest first.
+    pub fn failures(&self) -> &[FailureEvent] {
+        &self.failures
+    }
+
+    pub fn clear_failures(&mut self) {
+        self.failures.clear();
+    }
+
+    /// The intervals removed by failing since last asked, each index as it was when it went.
+    pub(crate) fn take_removed_intervals(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.removed_intervals)
+    }
+
+    /// Buckle pushes compressed past their Euler load and rupture pulls stretched past their
+    /// strength, for intervals with a material. Only a pretenst fabric does this, but a builder
+    /// may still be running its pretense steps, so removals are also noted for it to catch up on.
+    pub(crate) fn fail_overloaded(&mut self, failure_mode: FailureMode) {
+        let mut failed = Vec::new();
+        for (index, interval) in self.intervals.iter().enumerate() {
+            let Some(material) = interval.material else {
+                continue;
+            };
+            if interval.failed {
+                continue;
+            }
+            let newtons = material.axial_rigidity() * interval.stress as f64;
+            let failure = if interval.push {
+                let metres = (interval.calculate_current_length(&self.joints) * self.metres_per_unit) as f64;
+                let limit = material.buckling_load(metres);
+                (-newtons > limit).then_some((FailureKind::Buckling, limit))
+            } else {
+                let limit = material.rupture_load();
+                (newtons > limit).then_some((FailureKind::Rupture, limit))
+            };
+            if let Some((kind, limit)) = failure {
+                failed.push(index);
+                self.failures.push(FailureEvent {
+                    age: self.age,
+                    interval: index,
+                    alpha_index: interval.alpha_index,
+                    omega_index: interval.omega_index,
+                    kind,
+                    newtons: newtons as Real,
+                    limit: limit as Real,
+                });
+            }
+        }
+        for &index in failed.iter().rev() {
+            match failure_mode {
+                FailureMode::Off => {}
+                FailureMode::Remove => {
+                    self.remove_interval(index);
+                    self.removed_intervals.push(index);
+                }
+                FailureMode::Degrade => {
+                    let interval = &mut self.intervals[index];
+                    interval.failed = true;
+                    interval.stiffness *= DEGRADED_STIFFNESS;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::world::World;
+
+    /// A push squeezed by the pretenst factor beside a pull stretched five percent.
+    fn overloaded(failure_mode: FailureMode) -> (Fabric, World) {
+        let mut world = World::new();
+        world.set_failure_mode(failure_mode);
+        let mut fabric = Fabric::new(4);
+        for (x, z) in IntoIterator::into_iter([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.05, 1.0)]) {
+            fabric.create_joint(x, 1.0, z);
+        }
+        fabric.create_interval(0, 1, true, 1.0, 1.0, 1.0, 0.0);
+        fabric.create_interval(2, 3, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.set_materials(Material::SteelRod, Material::DyneemaLine);
+        fabric.stage = Stage::Pretenst;
+        (fabric, world)
+    }
+
+    #[test]
+    fn removes_failed_intervals() {
+        let (mut fabric, world) = overloaded(FailureMode::Remove);
+        fabric.iterate(&world);
+        let kinds: Vec<FailureKind> = fabric.failures().iter().map(|failure| failure.kind).collect();
+        assert_eq!(kinds, vec![FailureKind::Buckling, FailureKind::Rupture]);
+        assert!(fabric.failures().iter().all(|failure| failure.newtons.abs() > failure.limit));
+        assert_eq!(fabric.get_interval_count(), 0);
+    }
+
+    #[test]
+    fn degrades_failed_intervals_once() {
+        let (mut fabric, world) = overloaded(FailureMode::Degrade);
+        let stiffness = fabric.intervals[0].stiffness;
+        fabric.iterate(&world);
+        fabric.iterate(&world);
+        assert_eq!(fabric.failures().len(), 2);
+        assert_eq!(fabric.get_interval_count(), 2);
+        assert!(fabric.intervals.iter().all(|interval| interval.failed));
+        assert_eq!(fabric.intervals[0].stiffness, stiffness * DEGRADED_STIFFNESS);
+        let (mut fabric, world) = overloaded(FailureMode::Off);
+        fabric.iterate(&world);
+        assert!(fabric.failures().is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use crate::constants::Real;
+use crate::fabric::Fabric;
+
+/// How an interval's pull grows with its strain. Each law gives a stress, which is the strain that
+/// a linear spring of the same stiffness would need to pull as hard, so the linear law's stress is
+/// the strain itself. Laws other than linear are odd, pushing back when compressed just as they
+/// pull when stretched.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ForceLaw {
+    Linear,
+    /// A cable that only takes `slack_ratio` of its stiffness until it has been stretched by
+    /// `pre_stretch`, the way strands settle before a braided line pulls in earnest.
+    Bilinear { pre_stretch: Real, slack_ratio: Real },
+    /// An elastomer band stiffening as it stretches, with stiffness `exp(rate * strain)`.
+    Exponential { rate: Real },
+    /// A spring with a dashpot beside it, adding `damping` times the strain rate per unit of time.
+    KelvinVoigt { damping: Real },
+    /// A spring with a friction element beside it which builds up to `friction` over a strain
+    /// change of `slip` and then slides, so loading and unloading follow different paths.
+    Hysteretic { friction: Real, slip: Real },
+}
+
+impl ForceLaw {
+    /// The stress at this strain and strain rate, leaving out friction.
+    pub fn stress(&self, strain: Real, strain_rate: Real) -> Real {
+        match *self {
+            ForceLaw::Linear | ForceLaw::Hysteretic { .. } => strain,
+            ForceLaw::Bilinear { pre_stretch, slack_ratio } => {
+                let stretch = strain.abs();
+                if stretch <= pre_stretch {
+                    strain * slack_ratio
+                } else {
+                    strain.signum() * (pre_stretch * slack_ratio + stretch - pre_stretch)
+                }
+            }
+            ForceLaw::Exponential { rate: 0.0 } => strain,
+            ForceLaw::Exponential { rate } => strain.signum() * ((rate * strain.abs()).exp() - 1.0) / rate,
+            ForceLaw::KelvinVoigt { damping } => strain + damping * strain_rate,
+        }
+    }
+
+    /// The strain at which a resting interval feels this stress, undoing `stress` without friction.
+    pub fn strain(&self, stress: Real) -> Real {
+        match *self {
+            ForceLaw::Linear | ForceLaw::KelvinVoigt { .. } | ForceLaw::Hysteretic { .. } => stress,
+            ForceLaw::Bilinear { .. } if stress == 0.0 => 0.0,
+            ForceLaw::Bilinear { pre_stretch, slack_ratio } => {
+                let knee = pre_stretch * slack_ratio;
+                if stress.abs() <= knee {
+                    stress / slack_ratio
+                } else {
+                    stress.signum() * (stress.abs() - knee + pre_stretch)
+                }
+            }
+            ForceLaw::Exponential { rate: 0.0 } => stress,
+            ForceLaw::Exponential { rate } => stress.signum() * (rate * stress.abs()).ln_1p() / rate,
+        }
+    }
+
+    /// The integral of the stress from no strain up to this strain, leaving out damping and
+    /// friction, which is the stored energy over the rest length and the stiffness.
+    pub fn energy(&self, strain: Real) -> Real {
+        let stretch = strain.abs();
+        match *self {
+            ForceLaw::Linear | ForceLaw::KelvinVoigt { .. } | ForceLaw::Hysteretic { .. } => stretch * stretch / 2.0,
+            ForceLaw::Bilinear { pre_stretch, slack_ratio } => {
+                if stretch <= pre_stretch {
+                    slack_ratio * stretch * stretch / 2.0
+                } else {
+                    let beyond = stretch - pre_stretch;
+                    slack_ratio * pre_stretch * (pre_stretch / 2.0 + beyond) + beyond * beyond / 2.0
+                }
+            }
+            ForceLaw::Exponential { rate: 0.0 } => stretch * stretch / 2.0,
+            ForceLaw::Exponential { rate } => ((rate * stretch).exp() - 1.0 - rate * stretch) / (rate * rate),
+        }
+    }
+
+    /// How fast the stress grows with strain, leaving out damping and friction.
+    pub fn slope(&self, strain: Real) -> Real {
+        match *self {
+            ForceLaw::Linear | ForceLaw::KelvinVoigt { .. } | ForceLaw::Hysteretic { .. } => 1.0,
+            ForceLaw::Bilinear { pre_stretch, slack_ratio } => {
+                if strain.abs() <= pre_stretch { slack_ratio } else { 1.0 }
+            }
+            ForceLaw::Exponential { rate } => (rate * strain.abs()).exp(),
+        }
+    }
+
+    /// The stress in the friction element after the strain has changed by `change` since it was
+    /// `friction`. Always zero for laws without friction.
+    pub fn friction(&self, friction: Real, change: Real) -> Real {
+        match *self {
+            ForceLaw::Hysteretic { friction: limit, slip } => (friction + change * limit / slip).clamp(-limit, limit),
+            _ => 0.0,
+        }
+    }
+
+    /// How fast the friction stress grows with strain, zero while it slides.
+    pub fn friction_slope(&self, friction: Real) -> Real {
+        match *self {
+            ForceLaw::Hysteretic { friction: limit, slip } if friction.abs() < limit => limit / slip,
+            _ => 0.0,
+        }
+    }
+}
+
+impl Fabric {
+    pub fn set_force_law(&mut self, index: usize, force_law: ForceLaw) {
+        let interval = &mut self.intervals[index];
+        interval.force_law = force_law;
+        interval.friction = 0.0;
+        interval.friction_strain = interval.strain;
+    }
+
+    pub fn set_force_laws(&mut self, push: ForceLaw, pull: ForceLaw) {
+        for index in 0..self.intervals.len() {
+            let force_law = if self.intervals[index].push { push } else { pull };
+            self.set_force_law(index, force_law);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::*;
+
+    use super::*;
+    use crate::constants::*;
+    use crate::world::World;
+
+    fn stretched_pull(force_law: ForceLaw) -> (Fabric, World) {
+        let mut world = World::new();
+        world.set_float_value(WorldFeature::Drag, 0.0);
+        world.set_float_value(WorldFeature::Gravity, 0.0);
+        world.set_push_and_pull(true);
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 1.0, 0.0);
+        let omega = fabric.create_joint(1.01, 1.0, 0.0);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.set_force_law(0, force_law);
+        fabric.stage = Stage::Pretenst;
+        (fabric, world)
+    }
+
+    #[test]
+    fn laws_are_continuous_and_stiffen_as_described() {
+        let bilinear = ForceLaw::Bilinear { pre_stretch: 0.02, slack_ratio: 0.1 };
+        let knee = bilinear.stress(0.02, 0.0);
+        assert!((bilinear.stress(0.020001, 0.0) - knee).abs() < 1e-5);
+        assert_eq!(bilinear.slope(0.01), 0.1);
+        assert_eq!(bilinear.slope(0.03), 1.0);
+        let elastomer = ForceLaw::Exponential { rate: 10.0 };
+        assert!((elastomer.slope(0.0) - 1.0).abs() < 1e-6);
+        assert!(elastomer.stress(0.1, 0.0) > 0.1 && elastomer.slope(0.1) > elastomer.slope(0.05));
+        assert_eq!(elastomer.stress(-0.1, 0.0), -elastomer.stress(0.1, 0.0));
+        assert_eq!(ForceLaw::KelvinVoigt { damping: 2.0 }.stress(0.01, 0.005), 0.02);
+        for law in [bilinear, elastomer] {
+            for strain in [-0.1, -0.01, 0.0, 0.01, 0.02, 0.1] {
+                let stress = law.stress(strain, 0.0);
+                assert!((law.strain(stress) - strain).abs() < 1e-6, "{:?} at {}", law, strain);
+            }
+            let (strain, step) = (0.05, 1e-3);
+            let slope = (law.energy(strain + step) - law.energy(strain - step)) / (2.0 * step);
+            assert!((slope - law.stress(strain, 0.0)).abs() < 1e-3, "{:?}", law);
+        }
+    }
+
+    #[test]
+    fn dashpot_damps_ringing() {
+        let amplitude = |force_law: ForceLaw| {
+            let (mut fabric, world) = stretched_pull(force_law);
+            for _ in 0..20 {
+                fabric.iterate(&world);
+            }
+            let length = fabric.intervals[0].calculate_current_length(&fabric.joints);
+            (length - 1.0).abs()
+        };
+        let ringing = amplitude(ForceLaw::Linear);
+        let damped = amplitude(ForceLaw::KelvinVoigt { damping: 2.0 });
+        assert!(damped < ringing / 10.0, "{} against {}", damped, ringing);
+    }
+
+    #[test]
+    fn friction_makes_a_loop_that_costs_work() {
+        let work_around_a_cycle = |force_law: ForceLaw| {
+            let (mut fabric, world) = stretched_pull(force_law);
+            let mut work = 0.0;
+            let mut previous = (1.01, 0.0);
+            for tick in 0..=200 {
+                let x = 1.01 + 0.005 * (tick as Real * real_consts::TAU / 200.0).sin();
+                fabric.joints[1].location = Point3::new(x, 1.0, 0.0);
+                fabric.accumulate_forces(&world, 1.0);
+                fabric.intervals[0].advance(1.0);
+                // pulling the omega end out against the tension, which is what the interval feels there
+                let tension = fabric.intervals[0].tension(&world, Stage::Pretenst);
+                if tick > 0 {
+                    work += (tension + previous.1) / 2.0 * (x - previous.0);
+                }
+                previous = (x, tension);
+            }
+            work
+        };
+        let tension_scale = fabric_tension_at_unit_strain();
+        let elastic = work_around_a_cycle(ForceLaw::Linear);
+        let lossy = work_around_a_cycle(ForceLaw::Hysteretic { friction: 0.001, slip: 0.0005 });
+        assert!(elastic.abs() < tension_scale * 1e-6, "{}", elastic);
+        assert!(lossy > tension_scale * 1e-5, "{}", lossy);
+    }
+
+    fn fabric_tension_at_unit_strain() -> Real {
+        let (mut fabric, world) = stretched_pull(ForceLaw::Linear);
+        fabric.accumulate_forces(&world, 1.0);
+        fabric.intervals[0].tension(&world, Stage::Pretenst) / fabric.intervals[0].strain
+    }
+}
@@ -0,0 +1,220 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::fmt::{Display, Formatter};
+
+use nalgebra::*;
+
+use crate::constants::*;
+use crate::fabric::Fabric;
+use crate::world::World;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormFindingError {
+    DensityCount { expected: usize, found: usize },
+    WrongSign { interval: usize, force_density: Real },
+    NoAnchors,
+    AnchorOutOfRange { joint: usize },
+    Singular,
+}
+
+impl Display for FormFindingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormFindingError::DensityCount { expected, found } =>
+                write!(f, "expected {expected} force densities, found {found}"),
+            FormFindingError::WrongSign { interval, force_density } =>
+                write!(f, "interval {interval} has force density {force_density}, pushes need negative and pulls positive"),
+            FormFindingError::NoAnchors =>
+                write!(f, "form finding needs at least one anchor"),
+            FormFindingError::AnchorOutOfRange { joint } =>
+                write!(f, "anchor {joint} is not a joint"),
+            FormFindingError::Singular =>
+                write!(f, "these force densities do not determine the free joints"),
+        }
+    }
+}
+
+impl std::error::Error for FormFindingError {}
+
+impl Fabric {
+    /// Form-find with the force density method, instead of growing and pretensing. Every interval
+    /// gets a force density, its tension over its length, negative for pushes. The anchors stay
+    /// where they are and the other joints move to where the force densities balance.
+    ///
+    /// The fabric is left pretenst with rest lengths chosen so that the largest strain is `max_strain`
+    /// and all tensions are in proportion to the force densities, whatever the force laws. Only the free joints are then in
+    /// equilibrium: whatever the anchors carry must come from outside.
+    pub fn form_find(
+        &mut self,
+        world: &World,
+        force_densities: &[Real],
+        anchors: &[usize],
+        max_strain: Real,
+    ) -> Result<(), FormFindingError> {
+        let (expected, found) = (self.intervals.len(), force_densities.len());
+        if expected != found {
+            return Err(FormFindingError::DensityCount { expected, found });
+        }
+        for (index, (interval, &force_density)) in self.intervals.iter().zip(force_densities).enumerate() {
+            if interval.push && force_density >= 0.0 || !interval.push && force_density <= 0.0 {
+                return Err(FormFindingError::WrongSign { interval: index, force_density });
+            }
+        }
+        if anchors.is_empty() {
+            return Err(FormFindingError::NoAnchors);
+        }
+        if let Some(&joint) = anchors.iter().find(|&&joint| joint >= self.joints.len()) {
+            return Err(FormFindingError::AnchorOutOfRange { joint });
+        }
+        self.place_free_joints(force_densities, anchors)?;
+        self.set_rest_lengths(world, force_densities, max_strain);
+        for joint in &mut self.joints {
+            joint.velocity.fill(0.0);
+        }
+        self.stage = Stage::Pretenst;
+        self.pretensing_countdown = 0.0;
+        Ok(())
+    }
+
+    /// Solve `D_ff x_f = -D_fa x_a` for each coordinate, with `D` the force density matrix
+    /// split into free and anchored joints.
+    fn place_free_joints(&mut self, force_densities: &[Real], anchors: &[usize]) -> Result<(), FormFindingError> {
+        let free: Vec<usize> = (0..self.joints.len()).filter(|joint| !anchors.contains(joint)).collect();
+        let mut slot: Vec<Option<usize>> = vec![None; self.joints.len()];
+        for (row, &joint) in free.iter().enumerate() {
+            slot[joint] = Some(row);
+        }
+        let mut matrix = DMatrix::<f64>::zeros(free.len(), free.len());
+        let mut rhs = DMatrix::<f64>::zeros(free.len(), 3);
+        for (interval, &force_density) in self.intervals.iter().zip(force_densities) {
+            let force_density = force_density as f64;
+            let ends = [(interval.alpha_index, interval.omega_index), (interval.omega_index, interval.alpha_index)];
+            for (near, far) in IntoIterator::into_iter(ends) {
+                let Some(row) = slot[near] else {
+                    continue;
+                };
+                matrix[(row, row)] += force_density;
+                match slot[far] {
+                    Some(column) => matrix[(row, column)] -= force_density,
+                    None => {
+                        let anchor = self.joints[far].location.coords.cast::<f64>();
+                        for axis in 0..3 {
+                            rhs[(row, axis)] += force_density * anchor[axis];
+                        }
+                    }
+                }
+            }
+        }
+        let solution = matrix.lu().solve(&rhs).ok_or(FormFindingError::Singular)?;
+        if solution.iter().any(|coordinate| !coordinate.is_finite()) {
+            return Err(FormFindingError::Singular);
+        }
+        for (row, &joint) in free.iter().enumerate() {
+            let location = Vector3::new(solution[(row, 0)], solution[(row, 1)], solution[(row, 2)]);
+            self.joints[joint].location.coords = location.cast::<Real>();
+        }
+        Ok(())
+    }
+
+    /// Rest lengths for which the pretenst fabric feels tensions in proportion to the force densities.
+    /// Each interval's force law turns its stress back into the strain to give it, and the stresses
+    /// are scaled until the first interval reaches `max_strain`.
+    fn set_rest_lengths(&mut self, world: &World, force_densities: &[Real], max_strain: Real) {
+        let pretenst_factor = 1.0 + world.pretenst_factor;
+        let lengths: Vec<Real> = self.intervals
+            .iter()
+            .map(|interval| interval.calculate_current_length(&self.joints))
+            .collect();
+        // the force at unit stress, so these are the stresses that give tensions of the force densities
+        let stresses: Vec<Real> = self.intervals
+            .iter()
+            .zip(force_densities)
+            .zip(&lengths)
+            .map(|((interval, force_density), length)| {
+                let rigidity = interval.stiffness * interval.push_over_pull(world) * world.stiffness_factor / 2.0;
+                force_density * length / rigidity
+            })
+            .collect();
+        let scale = self.intervals
+            .iter()
+            .zip(&stresses)
+            .map(|(interval, stress)| interval.force_law.stress(max_strain, 0.0) / stress.abs())
+            .fold(Real::INFINITY, Real::min);
+        for (index, interval) in self.intervals.iter_mut().enumerate() {
+            let strain = interval.force_law.strain(scale * stresses[index]);
+            let ideal_length = lengths[index] / (1.0 + strain);
+            let rest_length = if interval.push { ideal_length / pretenst_factor } else { ideal_length };
+            interval.length_0 = rest_length;
+            interval.length_1 = rest_length;
+            interval.length_nuance = 0.0;
+            interval.attack = 0.0;
+            interval.decay = 0.0;
+            interval.friction = 0.0;
+            interval.friction_strain = strain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::force_law::ForceLaw;
+
+    fn tent() -> Fabric {
+        let mut fabric = Fabric::new(6);
+        for (x, z) in IntoIterator::into_iter([(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]) {
+            fabric.create_joint(x, 0.0, z);
+        }
+        let foot = fabric.create_joint(0.0, -3.0, 0.0);
+        let top = fabric.create_joint(0.3, 0.3, 0.3);
+        for corner in 0..4 {
+            fabric.create_interval(corner, top, false, 1.0, 1.0, 1.0, 0.0);
+        }
+        fabric.create_interval(foot, top, true, 1.0, 1.0, 1.0, 0.0);
+        fabric
+    }
+
+    #[test]
+    fn tent_pole_lifts_a_joint_between_four_anchors() {
+        let world = World::new();
+        let mut fabric = tent();
+        let (foot, top) = (4, 5);
+        let densities = [1.0, 1.0, 1.0, 1.0, -1.0];
+        assert_eq!(
+            fabric.form_find(&world, &densities, &[], 0.01),
+            Err(FormFindingError::NoAnchors),
+        );
+        fabric.form_find(&world, &densities, &[0, 1, 2, 3, foot], 0.01).unwrap();
+        let location = fabric.joints[top].location;
+        assert!((location - Point3::new(0.0, 1.0, 0.0)).magnitude() < 1e-6, "{}", location);
+        fabric.accumulate_forces(&world, 1.0);
+        let strains: Vec<Real> = fabric.intervals.iter().map(|interval| interval.strain).collect();
+        assert!(strains.iter().all(|strain| strain.abs() <= 0.0101), "{:?}", strains);
+        let tension = fabric.intervals[4].tension(&world, Stage::Pretenst).abs();
+        assert!(fabric.joints[top].force.magnitude() < tension * 1e-2);
+    }
+
+    #[test]
+    fn tensions_follow_the_densities_whatever_the_law() {
+        let world = World::new();
+        let mut fabric = tent();
+        fabric.set_force_laws(ForceLaw::Bilinear { pre_stretch: 0.005, slack_ratio: 0.2 }, ForceLaw::Exponential { rate: 20.0 });
+        fabric.set_force_law(0, ForceLaw::Hysteretic { friction: 0.01, slip: 0.001 });
+        let densities = [1.0, 2.0, 1.0, 2.0, -1.0];
+        fabric.form_find(&world, &densities, &[0, 1, 2, 3, 4], 0.01).unwrap();
+        fabric.accumulate_forces(&world, 1.0);
+        let largest = fabric.intervals.iter().map(|interval| interval.strain.abs()).fold(0.0, Real::max);
+        assert!((largest - 0.01).abs() < 1e-5, "largest strain {}", largest);
+        let ratios: Vec<Real> = fabric.intervals
+            .iter()
+            .zip(densities)
+            .map(|(interval, density)| {
+                interval.tension(&world, Stage::Pretenst) / (density * interval.calculate_current_length(&fabric.joints))
+            })
+            .collect();
+        assert!(ratios.iter().all(|ratio| (ratio - ratios[0]).abs() < ratios[0] * 1e-3), "{:?}", ratios);
+    }
+}
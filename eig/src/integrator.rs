@@ -0,0 +1,291 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use nalgebra::*;
+
+use crate::constants::*;
+use crate::fabric::Fabric;
+use crate::world::World;
+
+const CONJUGATE_GRADIENT_ITERATIONS: usize = 100;
+const CONJUGATE_GRADIENT_TOLERANCE: Real = 1e-10;
+
+impl Fabric {
+    /// Move every joint one tick of `world.time_step` with the world's integrator.
+    pub(crate) fn integrate(&mut self, world: &World, pretensing_nuance: Real) {
+        let dt = world.time_step;
+        self.accumulate_forces(world, pretensing_nuance);
+        let Some((gravity, drag)) = self.motion(world) else {
+            for joint in &mut self.joints {
+                joint.reaction = joint.support_reaction(0.0);
+                joint.location_physics(dt);
+            }
+            return;
+        };
+        for joint in &mut self.joints {
+            joint.reaction = joint.support_reaction(gravity);
+        }
+        match world.integrator {
+            Integrator::SemiImplicitEuler => {
+                for joint in &mut self.joints {
+                    joint.velocity_physics(world, gravity, drag, dt);
+                    joint.location_physics(dt);
+                }
+            }
+            Integrator::VelocityVerlet => {
+                self.velocity_verlet(world, pretensing_nuance, gravity, drag, dt);
+                self.surface_contact(world, gravity, dt);
+            }
+            Integrator::RungeKutta4 => {
+                self.runge_kutta_4(world, pretensing_nuance, gravity, drag, dt);
+                self.surface_contact(world, gravity, dt);
+            }
+            Integrator::ImplicitEuler => {
+                self.implicit_euler(world, pretensing_nuance, gravity, drag, dt);
+                self.surface_contact(world, gravity, dt);
+            }
+        }
+    }
+
+    /// Gravity and drag while joints move, or nothing while slack.
+    pub(crate) fn motion(&self, world: &World) -> Option<(Real, Real)> {
+        match self.stage {
+            Stage::Growing | Stage::Shaping | Stage::Pretensing => Some((0.0, world.shaping_drag)),
+            Stage::Slack => None,
+            Stage::Pretenst => Some((world.gravity, world.drag)),
+        }
+    }
+
+    pub(crate) fn accumulate_forces(&mut self, world: &World, pretensing_nuance: Real) {
+        for joint in &mut self.joints {
+            joint.reset();
+        }
+        self.kernel.accumulate(world, &mut self.joints, &mut self.intervals, self.stage, pretensing_nuance);
+        for load in &self.loads {
+            self.joints[load.joint].force += load.force_at(self.age);
+        }
+        if world.collision_mode == CollisionMode::Repel {
+            self.repel_contacts(world);
+        }
+    }
+
+    fn surface_contact(&mut self, world: &World, gravity: Real, dt: Real) {
+        if gravity == 0.0 {
+            return;
+        }
+        for joint in self.joints.iter_mut().filter(|joint| joint.location.y < 0.0) {
+            joint.surface_physics(world, dt);
+        }
+    }
+
+    fn velocity_verlet(&mut self, world: &World, pretensing_nuance: Real, gravity: Real, drag: Real, dt: Real) {
+        let accelerations: Vec<Vector3<Real>> = self.joints
+            .iter()
+            .map(|joint| joint.acceleration(gravity))
+            .collect();
+        for (joint, acceleration) in self.joints.iter_mut().zip(&accelerations) {
+            joint.location += joint.velocity * dt + acceleration * (dt * dt / 2.0);
+        }
+        self.accumulate_forces(world, pretensing_nuance);
+        for (joint, acceleration) in self.joints.iter_mut().zip(&accelerations) {
+            let average = (acceleration + joint.acceleration(gravity)) / 2.0;
+            joint.velocity = (joint.velocity + average * dt) * (1.0 - drag * dt);
+        }
+    }
+
+    fn runge_kutta_4(&mut self, world: &World, pretensing_nuance: Real, gravity: Real, drag: Real, dt: Real) {
+        let start: Vec<(Point3<Real>, Vector3<Real>)> = self.joints
+            .iter()
+            .map(|joint| (joint.location, joint.velocity))
+            .collect();
+        let mut sum: Vec<(Vector3<Real>, Vector3<Real>)> = vec![(zero(), zero()); start.len()];
+        let mut slopes = self.slopes(gravity, drag);
+        // each slope is weighted into the sum and then leads to the next trial state
+        for (weight, fraction) in IntoIterator::into_iter([(1.0, 0.5), (2.0, 0.5), (2.0, 1.0)]) {
+            for (index, joint) in self.joints.iter_mut().enumerate() {
+                let (location, velocity) = start[index];
+                let (location_slope, velocity_slope) = slopes[index];
+                sum[index].0 += location_slope * weight;
+                sum[index].1 += velocity_slope * weight;
+                joint.location = location + location_slope * (fraction * dt);
+                joint.velocity = velocity + velocity_slope * (fraction * dt);
+            }
+            self.accumulate_forces(world, pretensing_nuance);
+            slopes = self.slopes(gravity, drag);
+        }
+        for (index, joint) in self.joints.iter_mut().enumerate() {
+            let (location, velocity) = start[index];
+            let (location_slope, velocity_slope) = slopes[index];
+            joint.location = location + (sum[index].0 + location_slope) * (dt / 6.0);
+            joint.velocity = velocity + (sum[index].1 + velocity_slope) * (dt / 6.0);
+        }
+    }
+
+    fn slopes(&self, gravity: Real, drag: Real) -> Vec<(Vector3<Real>, Vector3<Real>)> {
+        self.joints
+            .iter()
+            .map(|joint| (joint.velocity, joint.acceleration(gravity) - joint.velocity * drag))
+            .collect()
+    }
+
+    /// Solve `(M(1 + drag dt) - dt² K) Δv = dt (F + M g - drag M v + dt K v)` for the change in velocity,
+    /// with K the axial stiffness of the intervals, by conjugate gradients. Pinned components are
+    /// filtered out of the system so they stay still.
+    fn implicit_euler(&mut self, world: &World, pretensing_nuance: Real, gravity: Real, drag: Real, dt: Real) {
+        let stiffnesses: Vec<Real> = self.intervals
+            .iter()
+            .map(|interval| interval.axial_stiffness(world, self.stage, pretensing_nuance))
+            .collect();
+        let velocities: Vec<Vector3<Real>> = self.joints.iter().map(|joint| joint.velocity).collect();
+        let stiffness_velocities = self.stiffness_product(&stiffnesses, &velocities);
+        let b: Vec<Vector3<Real>> = self.joints
+            .iter()
+            .zip(&stiffness_velocities)
+            .map(|(joint, stiffness_velocity)| {
+                let mass = joint.interval_mass;
+                let b = (joint.acceleration(gravity) * mass - joint.velocity * (drag * mass) + stiffness_velocity * dt) * dt;
+                joint.free(b)
+            })
+            .collect();
+        let system = |vectors: &[Vector3<Real>]| -> Vec<Vector3<Real>> {
+            let stiffness_vectors = self.stiffness_product(&stiffnesses, vectors);
+            self.joints
+                .iter()
+                .zip(vectors)
+                .zip(&stiffness_vectors)
+                .map(|((joint, vector), stiffness_vector)| {
+                    joint.free(vector * (joint.interval_mass * (1.0 + drag * dt)) - stiffness_vector * (dt * dt))
+                })
+                .collect()
+        };
+        let mut delta: Vec<Vector3<Real>> = vec![zero(); b.len()];
+        let mut residual = b.clone();
+        let mut direction = b;
+        let mut residual_squared = dot(&residual, &residual);
+        let tolerance = residual_squared * CONJUGATE_GRADIENT_TOLERANCE;
+        for _ in 0..CONJUGATE_GRADIENT_ITERATIONS {
+            if residual_squared <= tolerance {
+                break;
+            }
+            let system_direction = system(&direction);
+            let curvature = dot(&direction, &system_direction);
+            if curvature <= 0.0 {
+                break; // lost to rounding
+            }
+            let step = residual_squared / curvature;
+            for index in 0..delta.len() {
+                delta[index] += direction[index] * step;
+                residual[index] -= system_direction[index] * step;
+            }
+            let next_residual_squared = dot(&residual, &residual);
+            let beta = next_residual_squared / residual_squared;
+            for (direction, residual) in direction.iter_mut().zip(&residual) {
+                *direction = residual + *direction * beta;
+            }
+            residual_squared = next_residual_squared;
+        }
+        for (joint, delta) in self.joints.iter_mut().zip(&delta) {
+            joint.velocity += delta;
+            joint.location_physics(dt);
+        }
+    }
+
+    /// The stiffness matrix of all intervals times one vector per joint, without assembling the matrix.
+    fn stiffness_product(&self, stiffnesses: &[Real], vectors: &[Vector3<Real>]) -> Vec<Vector3<Real>> {
+        let mut product: Vec<Vector3<Real>> = vec![zero(); vectors.len()];
+        for (interval, stiffness) in self.intervals.iter().zip(stiffnesses) {
+            if *stiffness == 0.0 {
+                continue;
+            }
+            let unit = interval.unit;
+            let stretch = unit.dot(&(vectors[interval.omega_index] - vectors[interval.alpha_index]));
+            let pull = unit * (stiffness * stretch);
+            product[interval.alpha_index] += pull;
+            product[interval.omega_index] -= pull;
+        }
+        product
+    }
+}
+
+fn dot(a: &[Vector3<Real>], b: &[Vector3<Real>]) -> Real {
+    a.iter().zip(b).map(|(a, b)| a.dot(b)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joint::Joint;
+
+    const INTEGRATORS: [Integrator; 4] = [
+        Integrator::SemiImplicitEuler,
+        Integrator::VelocityVerlet,
+        Integrator::RungeKutta4,
+        Integrator::ImplicitEuler,
+    ];
+
+    /// A single stretched interval floating free of gravity, returning its final length.
+    fn spring(integrator: Integrator, stiffness_factor: Real, ticks: usize) -> Real {
+        let mut world = World::new();
+        world.set_integrator(integrator);
+        world.set_push_and_pull(true);
+        world.set_float_value(WorldFeature::Gravity, 0.0);
+        world.set_float_value(WorldFeature::Drag, 0.05);
+        world.set_float_value(WorldFeature::StiffnessFactor, stiffness_factor);
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 5.0, 0.0);
+        let omega = fabric.create_joint(2.0, 5.0, 0.0);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.stage = Stage::Pretenst;
+        for _ in 0..ticks {
+            fabric.integrate(&world, 1.0);
+        }
+        fabric.intervals[0].calculate_current_length(&fabric.joints)
+    }
+
+    #[test]
+    fn every_integrator_settles() {
+        for integrator in INTEGRATORS {
+            let length = spring(integrator, default_world_feature(WorldFeature::StiffnessFactor), 3000);
+            assert!((length - 1.0).abs() < 1e-3, "{:?} ended at {}", integrator, length);
+        }
+    }
+
+    #[test]
+    fn implicit_euler_survives_stiffness() {
+        let explicit = spring(Integrator::SemiImplicitEuler, 50.0, 100);
+        assert!(explicit.is_nan() || (explicit - 1.0).abs() > 1.0, "explicit ended at {}", explicit);
+        let implicit = spring(Integrator::ImplicitEuler, 50.0, 100);
+        assert!((implicit - 1.0).abs() < 1e-3, "implicit ended at {}", implicit);
+    }
+
+    #[test]
+    fn honours_the_time_step() {
+        let mut world = World::new();
+        for surface in [SurfaceCharacter::Sticky, SurfaceCharacter::Bouncy] {
+            world.set_surface_character(surface);
+            let mut whole = Joint::new(0.0, -0.2, 0.0);
+            whole.velocity = Vector3::new(0.5, -1.0, 0.25);
+            let mut halves = whole;
+            whole.surface_physics(&world, 1.0);
+            halves.surface_physics(&world, 0.5);
+            halves.surface_physics(&world, 0.5);
+            assert!((whole.velocity - halves.velocity).magnitude() < 1e-6, "{:?}", surface);
+        }
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 1.0, 0.0);
+        let omega = fabric.create_joint(1.0, 1.0, 0.0);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.twitch_interval(0, 4.0, 8.0, 2.0);
+        let mut whole = fabric.intervals[0];
+        let mut halves = fabric.intervals[0];
+        for _ in 0..14 {
+            whole.advance(1.0);
+            halves.advance(0.5);
+            halves.advance(0.5);
+            assert_eq!(whole.length_nuance, halves.length_nuance);
+        }
+        assert_eq!((whole.attack, whole.decay, whole.length_nuance), (0.0, 0.0, 0.0));
+    }
+}
@@ -3,31 +3,41 @@
  * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
  */
 
-extern crate fast_inv_sqrt;
-
+#[cfg(not(any(feature = "f64", feature = "exact-sqrt")))]
 use fast_inv_sqrt::InvSqrt32;
 use nalgebra::*;
 
 use crate::constants::*;
+use crate::force_law::ForceLaw;
 use crate::joint::Joint;
-use crate::view::View;
+use crate::material::Material;
+use crate::view::{render_float, View};
 use crate::world::World;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Copy)]
 pub struct Interval {
     pub(crate) alpha_index: usize,
     pub(crate) omega_index: usize,
     pub(crate) push: bool,
-    pub(crate) length_0: f32,
-    pub(crate) length_1: f32,
-    pub(crate) length_nuance: f32,
-    pub(crate) attack: f32,
-    pub(crate) decay: f32,
-    pub(crate) stiffness: f32,
-    pub(crate) linear_density: f32,
-    pub(crate) unit: Vector3<f32>,
-    pub(crate) strain: f32,
-    pub(crate) strain_nuance: f32,
+    pub(crate) length_0: Real,
+    pub(crate) length_1: Real,
+    pub(crate) length_nuance: Real,
+    pub(crate) attack: Real,
+    pub(crate) decay: Real,
+    pub(crate) stiffness: Real,
+    pub(crate) linear_density: Real,
+    pub(crate) material: Option<Material>,
+    pub(crate) failed: bool,
+    pub(crate) force_law: ForceLaw,
+    /// The friction stress and the strain at the end of the last tick, for a hysteretic law.
+    pub(crate) friction: Real,
+    pub(crate) friction_strain: Real,
+    pub(crate) unit: Vector3<Real>,
+    pub(crate) strain: Real,
+    /// What the force law makes of the strain, which is what the tension is in proportion to.
+    pub(crate) stress: Real,
+    pub(crate) strain_nuance: Real,
 }
 
 impl Interval {
@@ -35,10 +45,10 @@ impl Interval {
         alpha_index: usize,
         omega_index: usize,
         push: bool,
-        length_0: f32,
-        length_1: f32,
-        stiffness: f32,
-        attack: f32,
+        length_0: Real,
+        length_1: Real,
+        stiffness: Real,
+        attack: Real,
     ) -> Interval {
         Interval {
             alpha_index,
@@ -46,212 +56,289 @@ impl Interval {
             push,
             length_0,
             length_1,
-            length_nuance: 0_f32,
+            length_nuance: 0.0,
             attack,
-            decay: 0_f32,
-            stiffness: stiffness,
-            linear_density: if push { 1_f32 } else { 0.05_f32 },
+            decay: 0.0,
+            stiffness,
+            linear_density: if push { 1.0 } else { 0.05 },
+            material: None,
+            failed: false,
+            force_law: ForceLaw::Linear,
+            friction: 0.0,
+            friction_strain: 0.0,
             unit: zero(),
-            strain: 0_f32,
-            strain_nuance: 0_f32,
+            strain: 0.0,
+            stress: 0.0,
+            strain_nuance: 0.0,
         }
     }
 
     pub fn joint_removed(&mut self, index: usize) {
         if self.alpha_index > index {
-            self.alpha_index = self.alpha_index - 1;
+            self.alpha_index -= 1;
         }
         if self.omega_index > index {
-            self.omega_index = self.omega_index - 1;
+            self.omega_index -= 1;
         }
     }
 
-    pub fn alpha<'a>(&self, joints: &'a Vec<Joint>) -> &'a Joint {
+    pub fn alpha<'a>(&self, joints: &'a [Joint]) -> &'a Joint {
         &joints[self.alpha_index]
     }
 
-    pub fn omega<'a>(&self, joints: &'a Vec<Joint>) -> &'a Joint {
+    pub fn omega<'a>(&self, joints: &'a [Joint]) -> &'a Joint {
         &joints[self.omega_index]
     }
 
-    pub fn calculate_current_length_mut(&mut self, joints: &Vec<Joint>) -> f32 {
-        let alpha_location = &joints[self.alpha_index].location;
-        let omega_location = &joints[self.omega_index].location;
-        self.unit = omega_location - alpha_location;
-        let magnitude_squared = self.unit.magnitude_squared();
-        if magnitude_squared < 0.00001_f32 {
-            return 0.00001_f32;
+    /// Keep the unit vector along the vector from alpha to omega, returning its length.
+    pub(crate) fn set_unit(&mut self, alpha_to_omega: Vector3<Real>, magnitude_squared: Real) -> Real {
+        self.unit = alpha_to_omega;
+        if magnitude_squared < 0.00001 {
+            return 0.00001;
         }
-        let inverse_square_root = magnitude_squared.inv_sqrt32();
+        let inverse_square_root = inverse_sqrt(magnitude_squared);
         self.unit *= inverse_square_root;
-        1_f32 / inverse_square_root
+        1.0 / inverse_square_root
+    }
+
+    /// Whether the two share a joint.
+    pub fn touches(&self, other: &Interval) -> bool {
+        [self.alpha_index, self.omega_index].contains(&other.alpha_index)
+            || [self.alpha_index, self.omega_index].contains(&other.omega_index)
     }
 
-    pub fn calculate_current_length(&self, joints: &Vec<Joint>) -> f32 {
+    pub fn calculate_current_length(&self, joints: &[Joint]) -> Real {
         let alpha_location = &joints[self.alpha_index].location;
         let omega_location = &joints[self.omega_index].location;
         let unit = omega_location - alpha_location;
         let magnitude_squared = unit.magnitude_squared();
-        if magnitude_squared < 0.00001_f32 {
-            return 0.00001_f32;
+        if magnitude_squared < 0.00001 {
+            return 0.00001;
         }
-        let inverse_square_root = magnitude_squared.inv_sqrt32();
-        1_f32 / inverse_square_root
+        let inverse_square_root = inverse_sqrt(magnitude_squared);
+        1.0 / inverse_square_root
     }
 
-    pub fn physics(
+    /// Find the strain and stress at this length, which must come from `set_unit`. Returns the
+    /// force on the alpha joint, the opposite of the one on omega, and the mass each end carries.
+    pub(crate) fn respond(
         &mut self,
         world: &World,
-        joints: &mut Vec<Joint>,
+        joints: &[Joint],
         stage: Stage,
-        pretensing_nuance: f32,
-    ) {
+        pretensing_nuance: Real,
+        real_length: Real,
+    ) -> (Vector3<Real>, Real) {
         let ideal_length = self.ideal_length_now(world, stage, pretensing_nuance);
-        let real_length = self.calculate_current_length_mut(joints);
         self.strain = (real_length - ideal_length) / ideal_length;
         if !world.push_and_pull
-            && (self.push && self.strain > 0_f32 || !self.push && self.strain < 0_f32)
+            && (self.push && self.strain > 0.0 || !self.push && self.strain < 0.0)
         {
-            self.strain = 0_f32;
+            self.strain = 0.0;
         }
-        let push_over_pull = if self.push {
-            world.push_over_pull
+        self.stress = if self.strain == 0.0 {
+            0.0
         } else {
-            1_f32
-        };
-        let stiffness_factor = match stage {
-            Stage::Slack => 0_f32,
-            Stage::Growing | Stage::Shaping => world.shaping_stiffness_factor,
-            Stage::Pretensing | Stage::Pretenst => world.stiffness_factor,
+            let velocity = joints[self.omega_index].velocity - joints[self.alpha_index].velocity;
+            let strain_rate = velocity.dot(&self.unit) / ideal_length;
+            let friction = self.force_law.friction(self.friction, self.strain - self.friction_strain);
+            let stress = self.force_law.stress(self.strain, strain_rate) + friction;
+            match (world.push_and_pull, self.push) {
+                (true, _) => stress,
+                (false, true) => stress.min(0.0),
+                (false, false) => stress.max(0.0),
+            }
         };
-        let force = self.strain * self.stiffness * push_over_pull * stiffness_factor;
-        let force_vector: Vector3<f32> = self.unit.clone() * force / 2_f32;
-        joints[self.alpha_index].force += &force_vector;
-        joints[self.omega_index].force -= &force_vector;
-        let half_mass = ideal_length * self.linear_density / 2_f32;
-        joints[self.alpha_index].interval_mass += half_mass;
-        joints[self.omega_index].interval_mass += half_mass;
-        if self.attack > 0_f32 {
-            self.length_nuance += self.attack;
-            if self.length_nuance > 1_f32 {
-                self.attack = 0_f32; // done attacking
-                if self.decay == 0_f32 {
+        let force_vector: Vector3<Real> = self.unit * self.tension(world, stage);
+        let half_mass = ideal_length * self.linear_density / 2.0;
+        (force_vector, half_mass)
+    }
+
+    /// Move the rest length along by `dt` of its countdowns and let any friction settle, once per
+    /// tick, however many times the forces were evaluated.
+    pub fn advance(&mut self, dt: Real) {
+        self.friction = self.force_law.friction(self.friction, self.strain - self.friction_strain);
+        self.friction_strain = self.strain;
+        if self.attack > 0.0 {
+            self.length_nuance += self.attack * dt;
+            if self.length_nuance > 1.0 {
+                self.attack = 0.0; // done attacking
+                if self.decay == 0.0 {
                     self.length_0 = self.length_1; // both the same now
-                    self.length_nuance = 0_f32; // reset to zero
+                    self.length_nuance = 0.0; // reset to zero
                 } else {
-                    self.length_nuance = 1_f32 - self.decay; // first step back
+                    self.length_nuance = 1.0 - self.decay * dt; // first step back
                 }
             }
-        } else if self.decay > 0_f32 {
-            self.length_nuance -= self.decay;
-            if self.length_nuance <= 0_f32 {
-                self.length_nuance = 0_f32; // exactly zero
-                self.decay = 0_f32; // done decaying
+        } else if self.decay > 0.0 {
+            self.length_nuance -= self.decay * dt;
+            if self.length_nuance <= 0.0 {
+                self.length_nuance = 0.0; // exactly zero
+                self.decay = 0.0; // done decaying
             }
         }
     }
 
-    pub fn calculate_strain_nuance(&self, limits: &[f32; 4]) -> f32 {
+    /// How fast the force on either end grows with length, ignoring any sideways stiffness.
+    /// Zero when slack, so it must follow `respond`, which finds the strain when
+    /// `Kernel::accumulate` measures the interval.
+    pub fn axial_stiffness(&self, world: &World, stage: Stage, pretensing_nuance: Real) -> Real {
+        if self.strain == 0.0 {
+            return 0.0;
+        }
+        let ideal_length = self.ideal_length_now(world, stage, pretensing_nuance);
+        let push_over_pull = self.push_over_pull(world);
+        let stiffness_factor = Interval::stiffness_factor(world, stage);
+        let friction = self.force_law.friction(self.friction, self.strain - self.friction_strain);
+        let slope = self.force_law.slope(self.strain) + self.force_law.friction_slope(friction);
+        slope * self.stiffness * push_over_pull * stiffness_factor / ideal_length / 2.0
+    }
+
+    /// The pull felt at either end, negative when a push is compressed.
+    /// Like `axial_stiffness` it needs the strain found by `respond`.
+    pub fn tension(&self, world: &World, stage: Stage) -> Real {
+        let push_over_pull = self.push_over_pull(world);
+        let stiffness_factor = Interval::stiffness_factor(world, stage);
+        let force = self.stress * self.stiffness * push_over_pull * stiffness_factor;
+        force / 2.0
+    }
+
+    /// The energy stored by stretching or squeezing, which is the tension integrated over the
+    /// change in length. Like `tension` it needs the strain found by `respond`.
+    pub fn elastic_energy(&self, world: &World, stage: Stage, pretensing_nuance: Real) -> Real {
+        let ideal_length = self.ideal_length_now(world, stage, pretensing_nuance);
+        self.rigidity(world, stage) * ideal_length * self.force_law.energy(self.strain)
+    }
+
+    /// The tension of a unit stress.
+    pub(crate) fn rigidity(&self, world: &World, stage: Stage) -> Real {
+        let push_over_pull = self.push_over_pull(world);
+        let stiffness_factor = Interval::stiffness_factor(world, stage);
+        self.stiffness * push_over_pull * stiffness_factor / 2.0
+    }
+
+    pub(crate) fn push_over_pull(&self, world: &World) -> Real {
+        if self.push && self.material.is_none() {
+            world.push_over_pull
+        } else {
+            1.0
+        }
+    }
+
+    fn stiffness_factor(world: &World, stage: Stage) -> Real {
+        match stage {
+            Stage::Slack => 0.0,
+            Stage::Growing | Stage::Shaping => world.shaping_stiffness_factor,
+            Stage::Pretensing | Stage::Pretenst => world.stiffness_factor,
+        }
+    }
+
+    pub fn calculate_strain_nuance(&self, limits: &[Real; 4]) -> Real {
         let unsafe_nuance = if self.push {
             (self.strain - limits[1]) / (limits[0] - limits[1])
         } else {
             (self.strain - limits[2]) / (limits[3] - limits[2])
         };
-        if unsafe_nuance < 0_f32 {
-            0_f32
-        } else if unsafe_nuance > 1_f32 {
-            1_f32
-        } else {
-            unsafe_nuance
-        }
+        unsafe_nuance.clamp(0.0, 1.0)
     }
 
-    pub fn ideal_length_now(&self, world: &World, stage: Stage, pretensing_nuance: f32) -> f32 {
+    pub fn ideal_length_now(&self, world: &World, stage: Stage, pretensing_nuance: Real) -> Real {
         let ideal =
-            self.length_0 * (1_f32 - self.length_nuance) + self.length_1 * self.length_nuance;
+            self.length_0 * (1.0 - self.length_nuance) + self.length_1 * self.length_nuance;
         if self.push {
             match stage {
                 Stage::Slack => ideal,
                 Stage::Growing | Stage::Shaping => {
-                    let nuance = if self.attack == 0_f32 {
-                        1_f32
+                    let nuance = if self.attack == 0.0 {
+                        1.0
                     } else {
                         self.length_nuance
                     };
-                    ideal * (1_f32 + world.shaping_pretenst_factor * nuance)
+                    ideal * (1.0 + world.shaping_pretenst_factor * nuance)
                 }
-                Stage::Pretensing => ideal * (1_f32 + world.pretenst_factor * pretensing_nuance),
-                Stage::Pretenst => ideal * (1_f32 + world.pretenst_factor),
+                Stage::Pretensing => ideal * (1.0 + world.pretenst_factor * pretensing_nuance),
+                Stage::Pretenst => ideal * (1.0 + world.pretenst_factor),
             }
         } else {
             ideal
         }
     }
 
-    pub fn change_rest_length(&mut self, rest_length: f32, countdown: f32) {
+    pub fn change_rest_length(&mut self, rest_length: Real, countdown: Real) {
         self.length_0 = self.length_1;
         self.length_1 = rest_length;
-        self.length_nuance = 0_f32;
-        self.attack = 1_f32 / countdown;
-        self.decay = 0_f32;
+        self.length_nuance = 0.0;
+        self.attack = 1.0 / countdown;
+        self.decay = 0.0;
     }
 
-    pub fn twitch(&mut self, attack_countdown: f32, decay_countdown: f32, delta_size_nuance: f32) {
-        if self.length_nuance != 0_f32 {
+    pub fn twitch(&mut self, attack_countdown: Real, decay_countdown: Real, delta_size_nuance: Real) {
+        if self.length_nuance != 0.0 {
             // while changing? ignore!
             return;
         }
         self.length_1 = self.length_0 * delta_size_nuance;
-        self.length_nuance = 0_f32;
-        self.attack = 1_f32 / attack_countdown;
-        self.decay = 1_f32 / decay_countdown;
+        self.length_nuance = 0.0;
+        self.attack = 1.0 / attack_countdown;
+        self.decay = 1.0 / decay_countdown;
     }
 
-    pub fn multiply_rest_length(&mut self, factor: f32, countdown: f32) {
+    pub fn multiply_rest_length(&mut self, factor: Real, countdown: Real) {
         self.change_rest_length(self.length_1 * factor, countdown)
     }
 
-    pub fn project_line_locations<'a>(&self, view: &mut View, joints: &'a Vec<Joint>, extend: f32) {
+    pub fn project_line_locations(&self, view: &mut View, joints: &[Joint], extend: Real) {
         let alpha = &self.alpha(joints).location;
         let omega = &self.omega(joints).location;
-        view.line_locations.push(alpha.x - self.unit.x * extend);
-        view.line_locations.push(alpha.y - self.unit.y * extend);
-        view.line_locations.push(alpha.z - self.unit.z * extend);
-        view.line_locations.push(omega.x + self.unit.x * extend);
-        view.line_locations.push(omega.y + self.unit.y * extend);
-        view.line_locations.push(omega.z + self.unit.z * extend);
+        view.line_locations.push(render_float(alpha.x - self.unit.x * extend));
+        view.line_locations.push(render_float(alpha.y - self.unit.y * extend));
+        view.line_locations.push(render_float(alpha.z - self.unit.z * extend));
+        view.line_locations.push(render_float(omega.x + self.unit.x * extend));
+        view.line_locations.push(render_float(omega.y + self.unit.y * extend));
+        view.line_locations.push(render_float(omega.z + self.unit.z * extend));
     }
 
-    pub fn project_line_features<'a>(&self, view: &mut View, ideal_length: f32) {
-        view.unit_vectors.push(self.unit.x);
-        view.unit_vectors.push(self.unit.y);
-        view.unit_vectors.push(self.unit.z);
-        view.ideal_lengths.push(ideal_length);
-        view.strains.push(self.strain);
-        view.strain_nuances.push(self.strain_nuance);
-        view.stiffnesses.push(self.stiffness);
-        view.linear_densities.push(self.linear_density);
+    pub fn project_line_features(&self, view: &mut View, ideal_length: Real) {
+        view.unit_vectors.push(render_float(self.unit.x));
+        view.unit_vectors.push(render_float(self.unit.y));
+        view.unit_vectors.push(render_float(self.unit.z));
+        view.ideal_lengths.push(render_float(ideal_length));
+        view.strains.push(render_float(self.strain));
+        view.strain_nuances.push(render_float(self.strain_nuance));
+        view.stiffnesses.push(render_float(self.stiffness));
+        view.linear_densities.push(render_float(self.linear_density));
     }
 
     pub fn project_line_color_nuance(&self, view: &mut View) {
         let nuance = self.strain_nuance;
-        let anti = 1_f32 - self.strain_nuance;
-        let slack = 0.1_f32;
+        let anti = 1.0 - self.strain_nuance;
+        let slack = 0.1;
         if self.push {
-            Interval::project_line_rgb(view, 0_f32, anti, nuance)
-        } else if self.strain == 0_f32 {
+            Interval::project_line_rgb(view, 0.0, anti, nuance)
+        } else if self.strain == 0.0 {
             Interval::project_line_rgb(view, slack, slack, slack)
         } else {
-            Interval::project_line_rgb(view, nuance, anti, 0_f32)
+            Interval::project_line_rgb(view, nuance, anti, 0.0)
         }
     }
 
-    pub fn project_line_rgb(view: &mut View, r: f32, g: f32, b: f32) {
-        view.line_colors.push(r);
-        view.line_colors.push(g);
-        view.line_colors.push(b);
-        view.line_colors.push(r);
-        view.line_colors.push(g);
-        view.line_colors.push(b);
+    pub fn project_line_rgb(view: &mut View, r: Real, g: Real, b: Real) {
+        view.line_colors.push(render_float(r));
+        view.line_colors.push(render_float(g));
+        view.line_colors.push(render_float(b));
+        view.line_colors.push(render_float(r));
+        view.line_colors.push(render_float(g));
+        view.line_colors.push(render_float(b));
     }
 }
+
+/// Fast and good to a fraction of a percent, unless the precision matters more than the speed.
+#[cfg(not(any(feature = "f64", feature = "exact-sqrt")))]
+fn inverse_sqrt(value: Real) -> Real {
+    value.inv_sqrt32()
+}
+
+#[cfg(any(feature = "f64", feature = "exact-sqrt"))]
+fn inverse_sqrt(value: Real) -> Real {
+    1.0 / value.sqrt()
+}
@@ -4,93 +4,142 @@
  */
 
 use crate::constants::*;
-use crate::view::View;
+use crate::view::{render_float, View};
 use crate::world::World;
 use nalgebra::*;
 
-const RESURFACE: f32 = 0.01;
-const STICKY_UP_DRAG: f32 = 0.03;
-const STICKY_DOWN_DRAG: f32 = 0.3;
-const AMBIENT_MASS: f32 = 0.001_f32;
+const RESURFACE: Real = 0.01;
+const STICKY_UP_DRAG: Real = 0.03;
+const STICKY_DOWN_DRAG: Real = 0.3;
+const AMBIENT_MASS: Real = 0.001;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Copy)]
 pub struct Joint {
-    pub(crate) location: Point3<f32>,
-    pub(crate) force: Vector3<f32>,
-    pub(crate) velocity: Vector3<f32>,
-    pub(crate) interval_mass: f32,
+    pub(crate) location: Point3<Real>,
+    pub(crate) force: Vector3<Real>,
+    pub(crate) velocity: Vector3<Real>,
+    pub(crate) interval_mass: Real,
+    pub(crate) attached_mass: Real,
+    pub(crate) pinned: [bool; 3],
+    pub(crate) reaction: Vector3<Real>,
 }
 
 impl Joint {
-    pub fn new(x: f32, y: f32, z: f32) -> Joint {
+    pub fn new(x: Real, y: Real, z: Real) -> Joint {
         Joint {
             location: Point3::new(x, y, z),
             force: zero(),
             velocity: zero(),
             interval_mass: AMBIENT_MASS,
+            attached_mass: 0.0,
+            pinned: [false; 3],
+            reaction: zero(),
         }
     }
 
     pub fn reset(&mut self) {
         self.force = zero();
-        self.interval_mass = AMBIENT_MASS;
+        self.interval_mass = AMBIENT_MASS + self.attached_mass;
     }
 
     pub fn is_connected(&self) -> bool {
-        self.interval_mass > AMBIENT_MASS
+        self.interval_mass > AMBIENT_MASS + self.attached_mass
     }
 
-    pub fn velocity_physics(&mut self, world: &World, gravity: f32, drag: f32) {
+    pub fn is_pinned(&self) -> bool {
+        self.pinned.iter().any(|&pinned| pinned)
+    }
+
+    /// The vector without its pinned components.
+    pub fn free(&self, mut vector: Vector3<Real>) -> Vector3<Real> {
+        for axis in 0..3 {
+            if self.pinned[axis] {
+                vector[axis] = 0.0;
+            }
+        }
+        vector
+    }
+
+    /// What the supports must push with to keep the pinned components from accelerating.
+    pub fn support_reaction(&self, gravity: Real) -> Vector3<Real> {
+        let mut needed = self.force * -1.0;
+        needed.y += gravity * self.interval_mass;
+        needed - self.free(needed)
+    }
+
+    pub fn velocity_physics(&mut self, world: &World, gravity: Real, drag: Real, dt: Real) {
         let altitude = self.location.y;
-        if self.interval_mass == 0_f32 {
+        if self.interval_mass == 0.0 {
             self.velocity = zero();
-        } else if altitude >= 0_f32 || gravity == 0_f32 {
-            self.velocity.y -= gravity;
-            self.velocity += &self.force / self.interval_mass;
-            self.velocity *= 1_f32 - drag;
+        } else if altitude >= 0.0 || gravity == 0.0 {
+            self.velocity.y -= gravity * dt;
+            self.velocity += self.force / self.interval_mass * dt;
+            self.velocity *= 1.0 - drag * dt;
         } else {
-            let degree_submerged: f32 = if -altitude < 1_f32 { -altitude } else { 0_f32 };
-            let antigravity = world.antigravity * degree_submerged;
-            self.velocity += &self.force / self.interval_mass;
-            match world.surface_character {
-                SurfaceCharacter::Frozen => {
-                    self.velocity = zero();
-                    self.location.y = -RESURFACE;
-                }
-                SurfaceCharacter::Sticky => {
-                    if self.velocity.y < 0_f32 {
-                        let sticky_drag = 1_f32 - STICKY_DOWN_DRAG;
-                        self.velocity.x *= sticky_drag;
-                        self.velocity.y += antigravity;
-                        self.velocity.z *= sticky_drag;
-                    } else {
-                        let sticky_drag = 1_f32 - STICKY_UP_DRAG;
-                        self.velocity.x *= sticky_drag;
-                        self.velocity.y += antigravity;
-                        self.velocity.z *= sticky_drag;
-                    }
-                }
-                SurfaceCharacter::Bouncy => {
-                    let degree_cushioned: f32 = 1_f32 - degree_submerged;
-                    self.velocity *= degree_cushioned;
+            self.velocity += self.force / self.interval_mass * dt;
+            self.surface_physics(world, dt);
+        }
+        self.velocity = self.free(self.velocity);
+    }
+
+    /// The surface pushing back on a joint below it, once its velocity is updated. Its drag is
+    /// given per unit of time, so it compounds over however many ticks that takes.
+    pub fn surface_physics(&mut self, world: &World, dt: Real) {
+        if self.pinned[1] {
+            return;
+        }
+        let altitude = self.location.y;
+        let degree_submerged: Real = if -altitude < 1.0 { -altitude } else { 0.0 };
+        let antigravity = world.antigravity * degree_submerged * dt;
+        match world.surface_character {
+            SurfaceCharacter::Frozen => {
+                self.velocity = zero();
+                self.location.y = -RESURFACE;
+            }
+            SurfaceCharacter::Sticky => {
+                if self.velocity.y < 0.0 {
+                    let sticky_drag = (1.0 - STICKY_DOWN_DRAG).powf(dt);
+                    self.velocity.x *= sticky_drag;
+                    self.velocity.y += antigravity;
+                    self.velocity.z *= sticky_drag;
+                } else {
+                    let sticky_drag = (1.0 - STICKY_UP_DRAG).powf(dt);
+                    self.velocity.x *= sticky_drag;
                     self.velocity.y += antigravity;
+                    self.velocity.z *= sticky_drag;
                 }
             }
+            SurfaceCharacter::Bouncy => {
+                let degree_cushioned: Real = 1.0 - degree_submerged;
+                let cushioning = degree_cushioned.powf(dt);
+                // the lift of each part of the step is cushioned for what remains of it
+                let lifting = if degree_cushioned < 1.0 { (1.0 - cushioning) / (1.0 - degree_cushioned) } else { dt };
+                self.velocity *= cushioning;
+                self.velocity.y += world.antigravity * degree_submerged * lifting;
+            }
         }
+        self.velocity = self.free(self.velocity);
+    }
+
+    pub fn acceleration(&self, gravity: Real) -> Vector3<Real> {
+        let mut acceleration = self.force / self.interval_mass;
+        acceleration.y -= gravity;
+        self.free(acceleration)
     }
 
-    pub fn location_physics(&mut self) {
-        self.location += &self.velocity
+    pub fn location_physics(&mut self, dt: Real) {
+        self.location += self.velocity * dt
     }
 
     pub fn project(&self, view: &mut View) {
-        view.midpoint += &self.location.coords * self.interval_mass;
+        view.midpoint += self.location.coords * self.interval_mass;
         view.mass += self.interval_mass;
-        view.joint_locations.push(self.location.x);
-        view.joint_locations.push(self.location.y);
-        view.joint_locations.push(self.location.z);
-        view.joint_velocities.push(self.velocity.x);
-        view.joint_velocities.push(self.velocity.y);
-        view.joint_velocities.push(self.velocity.z);
+        view.joint_locations.push(render_float(self.location.x));
+        view.joint_locations.push(render_float(self.location.y));
+        view.joint_locations.push(render_float(self.location.z));
+        view.joint_velocities.push(render_float(self.velocity.x));
+        view.joint_velocities.push(render_float(self.velocity.y));
+        view.joint_velocities.push(render_float(self.velocity.z));
     }
 }
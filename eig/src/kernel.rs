@@ -0,0 +1,321 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use nalgebra::*;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::constants::*;
+use crate::fabric::Fabric;
+use crate::interval::Interval;
+use crate::joint::Joint;
+use crate::world::World;
+
+/// Fewer intervals than this are not worth handing out to threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_INTERVALS: usize = 2048;
+
+/// The joint locations and the intervals as a structure of arrays rather than an array of
+/// structures, so that measuring streams through memory, four intervals at a time on wasm with
+/// simd128, and the forces can be spread over threads.
+///
+/// The ends of the intervals stay loaded, kept in step by the fabric as intervals and joints come
+/// and go, while the joint locations are copied in each tick since the integrators move them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Kernel {
+    x: Vec<Real>,
+    y: Vec<Real>,
+    z: Vec<Real>,
+    alpha: Vec<usize>,
+    omega: Vec<usize>,
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    measured: [Vec<Real>; 4],
+    #[cfg(feature = "parallel")]
+    forces: Vec<Vector3<Real>>,
+    #[cfg(feature = "parallel")]
+    half_masses: Vec<Real>,
+    /// What each chunk of intervals adds to every joint, when spread over threads.
+    #[cfg(feature = "parallel")]
+    chunks: Vec<(Vec<Vector3<Real>>, Vec<Real>)>,
+}
+
+impl Kernel {
+    /// Find the force every interval puts on its joints and add them up, along with the mass each
+    /// joint carries. Same as having each interval add to its joints in turn, except that in
+    /// parallel the sums may round differently.
+    pub(crate) fn accumulate(
+        &mut self,
+        world: &World,
+        joints: &mut [Joint],
+        intervals: &mut [Interval],
+        stage: Stage,
+        pretensing_nuance: Real,
+    ) {
+        self.load(joints, intervals);
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        self.measure();
+        #[cfg(feature = "parallel")]
+        if intervals.len() >= PARALLEL_INTERVALS {
+            self.respond_in_parallel(world, joints, intervals, stage, pretensing_nuance);
+            self.gather(joints);
+            return;
+        }
+        self.respond(world, joints, intervals, stage, pretensing_nuance);
+    }
+
+    pub(crate) fn interval_created(&mut self, alpha: usize, omega: usize) {
+        self.alpha.push(alpha);
+        self.omega.push(omega);
+    }
+
+    pub(crate) fn interval_removed(&mut self, index: usize) {
+        self.alpha.remove(index);
+        self.omega.remove(index);
+    }
+
+    pub(crate) fn joint_removed(&mut self, index: usize) {
+        for end in self.alpha.iter_mut().chain(self.omega.iter_mut()) {
+            if *end > index {
+                *end -= 1;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.alpha.clear();
+        self.omega.clear();
+    }
+
+    /// Copy in the joint locations, and the interval ends too when they were never loaded, as
+    /// after cloning or reading a fabric.
+    fn load(&mut self, joints: &[Joint], intervals: &[Interval]) {
+        if self.alpha.len() != intervals.len() {
+            self.alpha = intervals.iter().map(|interval| interval.alpha_index).collect();
+            self.omega = intervals.iter().map(|interval| interval.omega_index).collect();
+        }
+        debug_assert!(intervals
+            .iter()
+            .zip(self.alpha.iter().zip(&self.omega))
+            .all(|(interval, (&alpha, &omega))| interval.alpha_index == alpha && interval.omega_index == omega));
+        self.x.clear();
+        self.y.clear();
+        self.z.clear();
+        for joint in joints {
+            self.x.push(joint.location.x);
+            self.y.push(joint.location.y);
+            self.z.push(joint.location.z);
+        }
+    }
+
+    /// The vector from alpha to omega of an interval, and its squared length.
+    fn measure_one(&self, index: usize) -> [Real; 4] {
+        let (a, o) = (self.alpha[index], self.omega[index]);
+        let (dx, dy, dz) = (self.x[o] - self.x[a], self.y[o] - self.y[a], self.z[o] - self.z[a]);
+        [dx, dy, dz, dx * dx + dy * dy + dz * dz]
+    }
+
+    /// Measured on the way, one at a time.
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    fn measured(&self, index: usize) -> (Vector3<Real>, Real) {
+        let [dx, dy, dz, length_squared] = self.measure_one(index);
+        (Vector3::new(dx, dy, dz), length_squared)
+    }
+
+    /// Measured beforehand, four at a time.
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    fn measured(&self, index: usize) -> (Vector3<Real>, Real) {
+        let [dx, dy, dz, length_squared] = &self.measured;
+        (Vector3::new(dx[index], dy[index], dz[index]), length_squared[index])
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    fn measure(&mut self) {
+        use core::arch::wasm32::*;
+        let count = self.alpha.len();
+        for array in &mut self.measured {
+            array.resize(count, 0.0);
+        }
+        let lanes = count / 4 * 4;
+        for at in (0..lanes).step_by(4) {
+            let (a, o) = (&self.alpha[at..at + 4], &self.omega[at..at + 4]);
+            let gather = |array: &[Real], ends: &[usize]| f32x4(array[ends[0]], array[ends[1]], array[ends[2]], array[ends[3]]);
+            let dx = f32x4_sub(gather(&self.x, o), gather(&self.x, a));
+            let dy = f32x4_sub(gather(&self.y, o), gather(&self.y, a));
+            let dz = f32x4_sub(gather(&self.z, o), gather(&self.z, a));
+            // summed in the same order as `measure_one` so the results are identical
+            let length_squared = f32x4_add(f32x4_add(f32x4_mul(dx, dx), f32x4_mul(dy, dy)), f32x4_mul(dz, dz));
+            for (array, lane) in self.measured.iter_mut().zip([dx, dy, dz, length_squared]) {
+                // SAFETY: the arrays were just sized to the interval count, and
+                // `at + 4 <= lanes <= count`, so the sixteen bytes written from `at` are four `f32`s
+                // inside each array. The wasm build is always single precision, and
+                // `write_unaligned` needs no alignment.
+                unsafe { (array[at..].as_mut_ptr() as *mut v128).write_unaligned(lane) };
+            }
+        }
+        for index in lanes..count {
+            let one = self.measure_one(index);
+            for (array, value) in self.measured.iter_mut().zip(one) {
+                array[index] = value;
+            }
+        }
+    }
+
+    /// Each interval responds to its length and adds to its joints in turn.
+    fn respond(&self, world: &World, joints: &mut [Joint], intervals: &mut [Interval], stage: Stage, pretensing_nuance: Real) {
+        for (index, interval) in intervals.iter_mut().enumerate() {
+            let (delta, length_squared) = self.measured(index);
+            let length = interval.set_unit(delta, length_squared);
+            let (force, half_mass) = interval.respond(world, joints, stage, pretensing_nuance, length);
+            let (alpha, omega) = (self.alpha[index], self.omega[index]);
+            joints[alpha].force += force;
+            joints[omega].force -= force;
+            joints[alpha].interval_mass += half_mass;
+            joints[omega].interval_mass += half_mass;
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn respond_in_parallel(&mut self, world: &World, joints: &[Joint], intervals: &mut [Interval], stage: Stage, pretensing_nuance: Real) {
+        let (mut forces, mut half_masses) = (std::mem::take(&mut self.forces), std::mem::take(&mut self.half_masses));
+        forces.resize(intervals.len(), zero());
+        half_masses.resize(intervals.len(), 0.0);
+        let kernel = &*self;
+        intervals
+            .par_iter_mut()
+            .zip(forces.par_iter_mut())
+            .zip(half_masses.par_iter_mut())
+            .enumerate()
+            .for_each(|(index, ((interval, force), half_mass))| {
+                let (delta, length_squared) = kernel.measured(index);
+                let length = interval.set_unit(delta, length_squared);
+                (*force, *half_mass) = interval.respond(world, joints, stage, pretensing_nuance, length);
+            });
+        self.forces = forces;
+        self.half_masses = half_masses;
+    }
+
+    /// The intervals are split into one chunk per thread which each add up into a buffer of their
+    /// own, and then each joint adds up the buffers in chunk order, so the result depends on the
+    /// number of threads but not on how they were scheduled.
+    #[cfg(feature = "parallel")]
+    fn gather(&mut self, joints: &mut [Joint]) {
+        let Kernel { alpha, omega, forces, half_masses, chunks, .. } = self;
+        let chunk = alpha.len().div_ceil(rayon::current_num_threads());
+        chunks.resize_with(alpha.len().div_ceil(chunk), Default::default);
+        chunks.par_iter_mut().enumerate().for_each(|(number, (chunk_forces, chunk_masses))| {
+            chunk_forces.clear();
+            chunk_forces.resize(joints.len(), zero());
+            chunk_masses.clear();
+            chunk_masses.resize(joints.len(), 0.0);
+            for index in number * chunk..((number + 1) * chunk).min(alpha.len()) {
+                let (alpha, omega) = (alpha[index], omega[index]);
+                chunk_forces[alpha] += forces[index];
+                chunk_forces[omega] -= forces[index];
+                chunk_masses[alpha] += half_masses[index];
+                chunk_masses[omega] += half_masses[index];
+            }
+        });
+        joints.par_iter_mut().enumerate().for_each(|(index, joint)| {
+            for (forces, masses) in chunks.iter() {
+                joint.force += forces[index];
+                joint.interval_mass += masses[index];
+            }
+        });
+    }
+}
+
+impl Fabric {
+    /// A cube of `side` joints along each edge, with a slightly short pull along every edge of
+    /// the lattice and a slightly long push across every cell. Something regular and easily
+    /// scaled to measure the kernel against.
+    pub fn lattice(side: usize) -> Fabric {
+        let mut fabric = Fabric::new(side * side * side);
+        let at = |x: usize, y: usize, z: usize| (x * side + y) * side + z;
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    fabric.create_joint(x as Real, y as Real + 1.0, z as Real);
+                }
+            }
+        }
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    let here = at(x, y, z);
+                    for (near, there) in [(x + 1 < side, (x + 1, y, z)), (y + 1 < side, (x, y + 1, z)), (z + 1 < side, (x, y, z + 1))] {
+                        if near {
+                            fabric.create_interval(here, at(there.0, there.1, there.2), false, 0.95, 0.95, 1.0, 0.0);
+                        }
+                    }
+                    if x + 1 < side && y + 1 < side && z + 1 < side {
+                        let length = Real::sqrt(3.0) * 1.05;
+                        fabric.create_interval(here, at(x + 1, y + 1, z + 1), true, length, length, 1.0, 0.0);
+                    }
+                }
+            }
+        }
+        fabric
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gathers_what_each_interval_adds() {
+        let world = World::new();
+        let mut fabric = Fabric::lattice(9); // enough intervals to go parallel
+        assert!(fabric.intervals.len() >= 2048);
+        fabric.stage = Stage::Pretenst;
+        fabric.accumulate_forces(&world, 1.0);
+        let mut expected: Vec<Vector3<Real>> = vec![zero(); fabric.joints.len()];
+        for interval in &fabric.intervals {
+            let force = interval.unit * interval.tension(&world, Stage::Pretenst);
+            expected[interval.alpha_index] += force;
+            expected[interval.omega_index] -= force;
+        }
+        let largest = expected.iter().map(|force| force.magnitude()).fold(0.0, Real::max);
+        assert!(largest > 0.0);
+        for (joint, expected) in fabric.joints.iter().zip(&expected) {
+            assert!((joint.force - expected).magnitude() <= largest * 1e-5);
+        }
+    }
+
+    #[test]
+    fn keeps_interval_ends_in_step() {
+        let world = World::new();
+        let mut fabric = Fabric::new(4);
+        let spare = fabric.create_joint(0.0, 1.0, 0.0);
+        let joints: Vec<usize> = (0..3).map(|index| fabric.create_joint(index as Real, 1.0, 1.0)).collect();
+        fabric.create_interval(joints[0], joints[1], false, 0.9, 0.9, 1.0, 0.0);
+        fabric.create_interval(joints[1], joints[2], false, 0.9, 0.9, 1.0, 0.0);
+        fabric.accumulate_forces(&world, 1.0);
+        fabric.remove_interval(0);
+        fabric.create_interval(joints[2], joints[0], true, 2.1, 2.1, 1.0, 0.0);
+        fabric.remove_joint(spare);
+        let ends = |fabric: &Fabric| -> Vec<(usize, usize)> {
+            fabric.intervals.iter().map(|interval| (interval.alpha_index, interval.omega_index)).collect()
+        };
+        let loaded: Vec<(usize, usize)> = fabric.kernel.alpha.iter().copied().zip(fabric.kernel.omega.iter().copied()).collect();
+        assert_eq!(loaded, ends(&fabric));
+        assert_eq!(loaded, vec![(1, 2), (2, 0)]);
+        fabric.accumulate_forces(&world, 1.0);
+    }
+
+    /// Only on wasm with simd128, run with a wasm runner such as wasmtime.
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    #[test]
+    fn measures_four_at_a_time_like_one_at_a_time() {
+        let fabric = Fabric::lattice(4);
+        let mut kernel = Kernel::default();
+        kernel.load(&fabric.joints, &fabric.intervals);
+        kernel.measure();
+        for index in 0..fabric.intervals.len() {
+            let [dx, dy, dz, length_squared] = kernel.measure_one(index);
+            assert_eq!(kernel.measured(index), (Vector3::new(dx, dy, dz), length_squared));
+        }
+    }
+}
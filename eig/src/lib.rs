@@ -1,10 +1,31 @@
-#![feature(let_else)]
+// converting between `Real` and the f64 of the matrix code is only a no-op in double precision
+#![cfg_attr(feature = "f64", allow(clippy::unnecessary_cast))]
 
-mod constants;
-mod fabric;
+#[cfg(all(feature = "f64", feature = "wasm"))]
+compile_error!("the browser build simulates in f32, so the f64 and wasm features do not go together");
+
+pub mod build;
+pub mod collision;
+pub mod constants;
+pub mod diagnostics;
+pub mod equilibrium;
+pub mod export;
+pub mod fabric;
 mod face;
+pub mod failure;
+pub mod force_law;
+pub mod form_finding;
+mod integrator;
 mod interval;
 mod joint;
-mod view;
-mod world;
-mod tenscript;
\ No newline at end of file
+mod kernel;
+pub mod material;
+pub mod modes;
+pub mod support;
+#[cfg(feature = "serde")]
+pub mod persist;
+pub mod view;
+pub mod world;
+pub mod tenscript;
+#[cfg(feature = "wasm")]
+mod wasm;
\ No newline at end of file
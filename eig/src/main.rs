@@ -0,0 +1,282 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::fs;
+use std::process::ExitCode;
+
+use eig::build::Builder;
+use eig::constants::{Stage, WorldFeature};
+use eig::export;
+use eig::fabric::Fabric;
+use eig::tenscript::{parse_all, FabricPlan};
+use eig::view::View;
+use eig::world::World;
+
+const USAGE: &str = "\
+usage:
+  eig check <file.ss>
+  eig run <file.ss> [--name <name>] [--max-frames <count>] [--export obj|json|bin --output <file>]";
+
+const DEFAULT_MAX_FRAMES: u32 = 100_000;
+
+/// Frames `check` builds each plan for, enough to plant the seed and grow the first twists.
+const CHECK_FRAMES: u32 = 100;
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Obj,
+    Json,
+    Binary,
+}
+
+struct RunOptions {
+    name: Option<String>,
+    max_frames: u32,
+    export: Option<(ExportFormat, String)>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.split_first() {
+        Some((command, rest)) if command == "check" => check(rest),
+        Some((command, rest)) if command == "run" => run(rest),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn check(args: &[String]) -> Result<(), String> {
+    let [path] = args else {
+        return Err(USAGE.to_string());
+    };
+    let plans = read_plans(path)?;
+    for (index, plan) in plans.iter().enumerate() {
+        let name = plan_name(plan, index);
+        let mut world = World::from_plan(plan).map_err(|error| format!("{name}: {error}"))?;
+        let mut builder = Builder::new(plan.clone(), &world).map_err(|error| format!("{name}: {error}"))?;
+        let mut fabric = Fabric::new(1000);
+        for _ in 0..CHECK_FRAMES {
+            if !builder.iterate(&mut fabric, &mut world) {
+                break;
+            }
+        }
+        if !joints_finite(&fabric, &world) {
+            return Err(format!("{name}: joints went astray within {CHECK_FRAMES} frames"));
+        }
+        println!("{name}: ok");
+    }
+    Ok(())
+}
+
+fn joints_finite(fabric: &Fabric, world: &World) -> bool {
+    let mut view = View::on_fabric(fabric);
+    view.render(fabric, world);
+    let mut locations = vec![0_f32; fabric.get_joint_count() as usize * 3];
+    view.copy_joint_locations_to(&mut locations);
+    locations.iter().all(|coordinate| coordinate.is_finite())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let Some((path, flags)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+    let options = run_options(flags)?;
+    let plans: Vec<(usize, FabricPlan)> = read_plans(path)?
+        .into_iter()
+        .enumerate()
+        .filter(|(_, plan)| options.name.is_none() || plan.name == options.name)
+        .collect();
+    match plans.len() {
+        0 => return Err(format!("no fabric to run in {path}")),
+        1 => {}
+        _ if options.export.is_some() => return Err("choose one fabric with --name to export".to_string()),
+        _ => {}
+    }
+    let mut unfinished = 0;
+    for (index, plan) in &plans {
+        let name = plan_name(plan, *index);
+        let (fabric, finished) = grow(plan, options.max_frames, &name)?;
+        if !finished {
+            unfinished += 1;
+            continue;
+        }
+        if let Some((format, output)) = &options.export {
+            let bytes = export_fabric(&fabric, *format)?;
+            fs::write(output, bytes).map_err(|error| format!("{output}: {error}"))?;
+            println!("  written to {output}");
+        }
+    }
+    if unfinished > 0 {
+        return Err(format!("{unfinished} fabric(s) did not finish within {} frames", options.max_frames));
+    }
+    Ok(())
+}
+
+fn run_options(flags: &[String]) -> Result<RunOptions, String> {
+    let mut options = RunOptions { name: None, max_frames: DEFAULT_MAX_FRAMES, export: None };
+    let mut format = None;
+    let mut output = None;
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags.next().ok_or_else(|| format!("{flag} needs a value\n{USAGE}"))?;
+        match flag.as_str() {
+            "--name" => options.name = Some(value.clone()),
+            "--max-frames" => {
+                options.max_frames = value.parse().map_err(|_| format!("bad frame count {value}"))?;
+            }
+            "--export" => {
+                format = Some(match value.as_str() {
+                    "obj" => ExportFormat::Obj,
+                    "json" => ExportFormat::Json,
+                    "bin" => ExportFormat::Binary,
+                    _ => return Err(format!("unknown export format {value}\n{USAGE}")),
+                });
+            }
+            "--output" => output = Some(value.clone()),
+            _ => return Err(format!("unknown option {flag}\n{USAGE}")),
+        }
+    }
+    options.export = match (format, output) {
+        (Some(format), Some(output)) => Some((format, output)),
+        (None, None) => None,
+        _ => return Err(format!("--export and --output go together\n{USAGE}")),
+    };
+    Ok(options)
+}
+
+fn read_plans(path: &str) -> Result<Vec<FabricPlan>, String> {
+    let source = fs::read_to_string(path).map_err(|error| format!("{path}: {error}"))?;
+    parse_all(&source).map_err(|error| format!("{path}\n{error}"))
+}
+
+fn plan_name(plan: &FabricPlan, index: usize) -> String {
+    match &plan.name {
+        Some(name) => name.clone(),
+        None => format!("fabric #{}", index + 1),
+    }
+}
+
+/// Run the plan to completion, printing frames spent per stage and how settled the result is.
+fn grow(plan: &FabricPlan, max_frames: u32, name: &str) -> Result<(Fabric, bool), String> {
+    let mut world = World::from_plan(plan).map_err(|error| format!("{name}: {error}"))?;
+    let mut fabric = Fabric::new(1000);
+    let mut builder = Builder::new(plan.clone(), &world).map_err(|error| format!("{name}: {error}"))?;
+    let mut stages: Vec<(Stage, u32)> = Vec::new();
+    let mut frames = 0;
+    let mut finished = false;
+    while frames < max_frames {
+        frames += 1;
+        let busy = builder.iterate(&mut fabric, &mut world);
+        match stages.last_mut() {
+            Some((stage, count)) if *stage == fabric.get_stage() => *count += 1,
+            _ => stages.push((fabric.get_stage(), 1)),
+        }
+        if !busy {
+            finished = true;
+            break;
+        }
+    }
+    let iterations_per_frame = world.get_float_value(WorldFeature::IterationsPerFrame) as u32;
+    let outcome = if finished { "finished" } else { "did not finish" };
+    println!("{name}: {outcome} after {frames} frames of {iterations_per_frame} iterations");
+    for (stage, count) in &stages {
+        println!("  {:<12}{count:>8} frames", format!("{stage:?}"));
+    }
+    let mut view = View::on_fabric(&fabric);
+    view.render(&fabric, &world);
+    let joint_count = fabric.get_joint_count() as usize;
+    let mut velocities = vec![0_f32; joint_count * 3];
+    view.copy_joint_velocities_to(&mut velocities);
+    let max_speed = velocities
+        .chunks(3)
+        .map(|v| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt())
+        .fold(0_f32, f32::max);
+    println!(
+        "  {} joints, {} intervals, {} faces, radius {:.3}, max speed {max_speed:.3e}",
+        joint_count,
+        fabric.get_interval_count(),
+        fabric.get_face_count(),
+        view.radius(),
+    );
+    Ok((fabric, finished))
+}
+
+fn export_fabric(fabric: &Fabric, format: ExportFormat) -> Result<Vec<u8>, String> {
+    match format {
+        ExportFormat::Obj => Ok(export::obj(fabric).into_bytes()),
+        #[cfg(feature = "serde")]
+        ExportFormat::Json => eig::persist::to_json(fabric).map(String::into_bytes).map_err(|error| error.to_string()),
+        #[cfg(feature = "serde")]
+        ExportFormat::Binary => eig::persist::to_bytes(fabric).map_err(|error| error.to_string()),
+        #[cfg(not(feature = "serde"))]
+        ExportFormat::Json | ExportFormat::Binary => Err("json and bin export need the serde feature".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn source_file(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("eig-{}-{}.ss", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn reads_run_options() {
+        let options = run_options(&args("--name Knee --max-frames 10 --export obj --output knee.obj")).unwrap();
+        assert_eq!(options.name.as_deref(), Some("Knee"));
+        assert_eq!(options.max_frames, 10);
+        assert!(matches!(options.export, Some((ExportFormat::Obj, ref output)) if output == "knee.obj"));
+        assert_eq!(run_options(&[]).unwrap().max_frames, DEFAULT_MAX_FRAMES);
+        for bad in ["--name", "--max-frames many", "--export obj", "--export png --output x", "--speed 2"] {
+            assert!(run_options(&args(bad)).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn checks_by_building() {
+        let good = source_file("good", "(fabric (name \"Tower\") (build (grow A+ 2)))");
+        assert_eq!(check(std::slice::from_ref(&good)), Ok(()));
+        let bad = source_file("bad", "(fabric (name \"Lost\") (build (grow A+ 2)) (shape (join :nowhere)))");
+        let error = check(std::slice::from_ref(&bad)).unwrap_err();
+        assert!(error.starts_with("Lost: mark :nowhere"), "{}", error);
+        assert!(check(&[]).is_err());
+        fs::remove_file(good).unwrap();
+        fs::remove_file(bad).unwrap();
+    }
+
+    #[test]
+    fn exports_fabrics() {
+        let plan = eig::tenscript::parse("(fabric (build (seed :left)))").unwrap();
+        let (fabric, finished) = grow(&plan, 1000, "seed").unwrap();
+        assert!(finished);
+        let obj = String::from_utf8(export_fabric(&fabric, ExportFormat::Obj).unwrap()).unwrap();
+        assert!(obj.starts_with("# 8 joints, 12 intervals, 2 faces\n"));
+        let json = export_fabric(&fabric, ExportFormat::Json);
+        let binary = export_fabric(&fabric, ExportFormat::Binary);
+        #[cfg(feature = "serde")]
+        {
+            let json: Fabric = eig::persist::from_json(&String::from_utf8(json.unwrap()).unwrap()).unwrap();
+            let binary: Fabric = eig::persist::from_bytes(&binary.unwrap()).unwrap();
+            for restored in [json, binary] {
+                assert_eq!(export::obj(&restored), obj);
+            }
+        }
+        #[cfg(not(feature = "serde"))]
+        assert!(json.is_err() && binary.is_err());
+    }
+}
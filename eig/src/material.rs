@@ -0,0 +1,261 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::f64::consts::PI;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::constants::Real;
+use crate::fabric::Fabric;
+use crate::world::World;
+
+/// Newtons of axial rigidity for each unit of interval stiffness. A 10mm steel rod comes out
+/// at about 16 and a 3mm dyneema line at about 0.7, where a plain interval has 1 and a plain
+/// push gets 3 from push-over-pull. The world's stiffness factor then scales every interval
+/// down alike, so a fabric of real stock ticks as stably as a plain one whose pushes are that
+/// much stiffer, and `PhysicalScale` turns the resulting tensions back into newtons.
+const NEWTONS_PER_STIFFNESS: f64 = 1e6;
+/// Kilograms per metre for each unit of interval linear density.
+const KILOGRAMS_PER_METRE_PER_DENSITY: f64 = 1_f64;
+
+/// Stock that a real model is built from.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Material {
+    SteelRod,
+    AluminiumTube,
+    Bamboo,
+    DyneemaLine,
+}
+
+/// In SI units: pascals, square metres, kilograms per cubic metre and metres to the fourth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialProperties {
+    pub name: &'static str,
+    pub youngs_modulus: f64,
+    pub cross_section: f64,
+    pub density: f64,
+    /// The second moment of area of the cross section, which resists bending.
+    pub second_moment: f64,
+    /// The stress at which it breaks when pulled.
+    pub tensile_strength: f64,
+}
+
+impl Material {
+    pub const CATALOG: [Material; 4] = [
+        Material::SteelRod,
+        Material::AluminiumTube,
+        Material::Bamboo,
+        Material::DyneemaLine,
+    ];
+
+    pub fn properties(self) -> MaterialProperties {
+        match self {
+            Material::SteelRod => MaterialProperties {
+                name: "steel rod 10mm",
+                youngs_modulus: 200e9,
+                cross_section: circle(0.010),
+                density: 7850.0,
+                second_moment: disc(0.010),
+                tensile_strength: 400e6,
+            },
+            Material::AluminiumTube => MaterialProperties {
+                name: "aluminium tube 25x2mm",
+                youngs_modulus: 69e9,
+                cross_section: circle(0.025) - circle(0.021),
+                density: 2700.0,
+                second_moment: disc(0.025) - disc(0.021),
+                tensile_strength: 290e6,
+            },
+            Material::Bamboo => MaterialProperties {
+                name: "bamboo 40mm",
+                youngs_modulus: 18e9,
+                cross_section: circle(0.040) - circle(0.032),
+                density: 700.0,
+                second_moment: disc(0.040) - disc(0.032),
+                tensile_strength: 150e6,
+            },
+            Material::DyneemaLine => MaterialProperties {
+                name: "dyneema line 3mm",
+                youngs_modulus: 100e9,
+                cross_section: circle(0.003),
+                density: 970.0,
+                second_moment: disc(0.003),
+                tensile_strength: 2e9,
+            },
+        }
+    }
+
+    /// Young's modulus times cross section, in newtons: the force that would double the length.
+    pub fn axial_rigidity(self) -> f64 {
+        let properties = self.properties();
+        properties.youngs_modulus * properties.cross_section
+    }
+
+    /// In kilograms per metre.
+    pub fn linear_density(self) -> f64 {
+        let properties = self.properties();
+        properties.density * properties.cross_section
+    }
+
+    /// The Euler buckling load in newtons of a pinned member this long.
+    pub fn buckling_load(self, metres: f64) -> f64 {
+        let properties = self.properties();
+        PI * PI * properties.youngs_modulus * properties.second_moment / (metres * metres)
+    }
+
+    /// The pull in newtons that breaks it.
+    pub fn rupture_load(self) -> f64 {
+        let properties = self.properties();
+        properties.tensile_strength * properties.cross_section
+    }
+}
+
+fn circle(diameter: f64) -> f64 {
+    PI * diameter * diameter / 4_f64
+}
+
+/// The second moment of area of a solid circle.
+fn disc(diameter: f64) -> f64 {
+    PI * diameter.powi(4) / 64_f64
+}
+
+/// How much one model unit is in the real world, for a fabric whose intervals all have materials.
+/// Forces are for the pretenst stage, while time and gravity stay in model units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicalScale {
+    pub metres_per_unit: Real,
+    pub newtons_per_unit: Real,
+    pub kilograms_per_unit: Real,
+}
+
+impl Fabric {
+    /// Make an interval of real stock, giving it the material's stiffness and linear density.
+    /// A push of some material is no longer stiffened by push-over-pull.
+    pub fn set_material(&mut self, index: usize, material: Material) {
+        let interval = &mut self.intervals[index];
+        interval.material = Some(material);
+        interval.stiffness = (material.axial_rigidity() / NEWTONS_PER_STIFFNESS) as Real;
+        interval.linear_density = (material.linear_density() / KILOGRAMS_PER_METRE_PER_DENSITY) as Real;
+    }
+
+    pub fn set_materials(&mut self, push: Material, pull: Material) {
+        for index in 0..self.intervals.len() {
+            let material = if self.intervals[index].push { push } else { pull };
+            self.set_material(index, material);
+        }
+    }
+
+    pub fn set_metres_per_unit(&mut self, metres_per_unit: Real) {
+        self.metres_per_unit = metres_per_unit;
+    }
+
+    /// Whether every interval has a material, so the fabric can be measured in real units.
+    pub fn is_physical(&self) -> bool {
+        self.intervals.iter().all(|interval| interval.material.is_some())
+    }
+
+    pub fn physical_scale(&self, world: &World) -> PhysicalScale {
+        // an interval's tension is strain times stiffness times the stiffness factor over two
+        let newtons_per_unit = 2_f64 * NEWTONS_PER_STIFFNESS / world.stiffness_factor as f64;
+        let kilograms_per_unit = KILOGRAMS_PER_METRE_PER_DENSITY * self.metres_per_unit as f64;
+        PhysicalScale {
+            metres_per_unit: self.metres_per_unit,
+            newtons_per_unit: newtons_per_unit as Real,
+            kilograms_per_unit: kilograms_per_unit as Real,
+        }
+    }
+
+    /// The tension in newtons, negative for compression, if the interval has a material. This
+    /// is the force the simulation applies, which only matches the material's axial rigidity
+    /// times the stress while pretensing and pretenst, so a slack fabric reads zero and a
+    /// shaping one reads its shaping forces.
+    pub fn interval_newtons(&self, index: usize, world: &World) -> Option<Real> {
+        let interval = &self.intervals[index];
+        interval.material?;
+        Some(interval.tension(world, self.stage) * self.physical_scale(world).newtons_per_unit)
+    }
+
+    pub fn interval_metres(&self, index: usize) -> Real {
+        self.intervals[index].calculate_current_length(&self.joints) * self.metres_per_unit
+    }
+
+    /// The total mass in kilograms, if every interval has a material.
+    pub fn kilograms(&self) -> Option<Real> {
+        self.intervals
+            .iter()
+            .map(|interval| {
+                let material = interval.material?;
+                let metres = interval.calculate_current_length(&self.joints) * self.metres_per_unit;
+                Some(material.linear_density() * metres as f64)
+            })
+            .sum::<Option<f64>>()
+            .map(|kilograms| kilograms as Real)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+
+    #[test]
+    fn reports_real_loads() {
+        let world = World::new();
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 1.0, 0.0);
+        let omega = fabric.create_joint(1.01, 1.0, 0.0);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        assert!(!fabric.is_physical());
+        assert_eq!(fabric.interval_newtons(0, &world), None);
+        fabric.set_materials(Material::SteelRod, Material::DyneemaLine);
+        fabric.set_metres_per_unit(0.5);
+        fabric.stage = Stage::Pretenst;
+        fabric.accumulate_forces(&world, 1.0);
+        let newtons = fabric.interval_newtons(0, &world).unwrap();
+        let expected = Material::DyneemaLine.axial_rigidity() as Real * fabric.intervals[0].strain;
+        assert!((newtons - expected).abs() <= expected * 1e-5, "{} instead of {}", newtons, expected);
+        fabric.stage = Stage::Shaping;
+        let shaping = fabric.interval_newtons(0, &world).unwrap();
+        let ratio = world.shaping_stiffness_factor / world.stiffness_factor;
+        assert!((shaping - expected * ratio).abs() <= expected * ratio * 1e-5, "{} while shaping", shaping);
+        fabric.stage = Stage::Slack;
+        assert_eq!(fabric.interval_newtons(0, &world), Some(0.0));
+        let scale = fabric.physical_scale(&world);
+        let kilograms = fabric.kilograms().unwrap();
+        let model_mass = fabric.intervals[0].linear_density * fabric.intervals[0].calculate_current_length(&fabric.joints);
+        assert!((model_mass * scale.kilograms_per_unit - kilograms).abs() < kilograms * 1e-4);
+    }
+
+    #[test]
+    fn pretenses_real_stock() {
+        let plan = crate::tenscript::parse("(fabric (build (seed :left) (grow A+ 2)) (pretense))").unwrap();
+        let mut world = World::from_plan(&plan).unwrap();
+        let mut fabric = Fabric::new(100);
+        let mut builder = crate::build::Builder::new(plan, &world).unwrap();
+        while fabric.stage != Stage::Slack {
+            assert!(builder.iterate(&mut fabric, &mut world));
+        }
+        fabric.set_materials(Material::SteelRod, Material::DyneemaLine);
+        fabric.set_metres_per_unit(0.1);
+        while builder.iterate(&mut fabric, &mut world) {}
+        for _ in 0..1000 {
+            fabric.iterate(&world);
+        }
+        assert_eq!(fabric.stage, Stage::Pretenst);
+        let speed = fabric.joints.iter().map(|joint| joint.velocity.magnitude()).fold(0.0, Real::max);
+        assert!(speed < 1e-4, "still moving at {}", speed);
+        for (index, interval) in fabric.intervals.iter().enumerate() {
+            let newtons = fabric.interval_newtons(index, &world).unwrap();
+            let material = interval.material.unwrap();
+            let expected = material.axial_rigidity() as Real * interval.stress;
+            assert!((newtons - expected).abs() <= expected.abs() * 1e-5 + 1e-3, "{} instead of {}", newtons, expected);
+            assert!(if interval.push { newtons < 0.0 } else { newtons >= 0.0 }, "interval {} at {}N", index, newtons);
+        }
+    }
+}
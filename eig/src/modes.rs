@@ -0,0 +1,111 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::f64::consts::TAU;
+
+use nalgebra::*;
+
+use crate::constants::Real;
+use crate::fabric::Fabric;
+use crate::view::View;
+use crate::world::World;
+
+/// One way a fabric vibrates about its current shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mode {
+    /// The squared angular frequency, negative when the fabric is unstable in this shape.
+    pub eigenvalue: Real,
+    /// Cycles per unit of time, which is a tick at the default time step. Zero for rigid motion.
+    pub frequency: Real,
+    /// How far each joint moves, scaled so the joint moving most moves one.
+    pub shape: Vec<Vector3<Real>>,
+}
+
+impl Fabric {
+    /// The `count` lowest modes, from the tangent stiffness in the current configuration and the
+    /// lumped mass of the intervals at each joint. The first six describe rigid motion unless the
+    /// fabric is pinned, and pinned joints stay still in every mode.
+    pub fn modes(&mut self, world: &World, count: usize) -> Vec<Mode> {
+        let pretensing_nuance = world.pretensing_nuance(self);
+        self.accumulate_forces(world, pretensing_nuance);
+        let free = self.free_coordinates();
+        let stiffness = self.tangent_stiffness(world, pretensing_nuance)
+            .select_rows(&free)
+            .select_columns(&free);
+        let inverse_root_mass: Vec<f64> = free
+            .iter()
+            .map(|&coordinate| 1_f64 / (self.joints[coordinate / 3].interval_mass as f64).sqrt())
+            .collect();
+        let size = inverse_root_mass.len();
+        let scaled = DMatrix::from_fn(size, size, |row, column| {
+            stiffness[(row, column)] * inverse_root_mass[row] * inverse_root_mass[column]
+        });
+        let eigen = scaled.symmetric_eigen();
+        let mut order: Vec<usize> = (0..size).collect();
+        order.sort_by(|&a, &b| eigen.eigenvalues[a].total_cmp(&eigen.eigenvalues[b]));
+        order
+            .into_iter()
+            .take(count)
+            .map(|index| {
+                let eigenvalue = eigen.eigenvalues[index];
+                let column = eigen.eigenvectors.column(index);
+                let mut shape: Vec<Vector3<Real>> = vec![zero(); self.joints.len()];
+                for (row, &coordinate) in free.iter().enumerate() {
+                    shape[coordinate / 3][coordinate % 3] = (column[row] * inverse_root_mass[row]) as Real;
+                }
+                let largest = shape.iter().map(|displacement| displacement.magnitude()).fold(0.0, Real::max);
+                if largest > 0.0 {
+                    shape.iter_mut().for_each(|displacement| *displacement /= largest);
+                }
+                Mode {
+                    eigenvalue: eigenvalue as Real,
+                    frequency: (eigenvalue.max(0_f64).sqrt() / TAU) as Real,
+                    shape,
+                }
+            })
+            .collect()
+    }
+}
+
+impl View {
+    /// Render the fabric displaced along a mode, `amplitude * sin(phase)` at the joint moving most.
+    /// Sweeping the phase through a full turn animates one cycle of the vibration.
+    pub fn render_mode(&mut self, fabric: &Fabric, world: &World, mode: &Mode, amplitude: Real, phase: Real) {
+        let mut displaced = fabric.clone();
+        let scale = amplitude * phase.sin();
+        for (joint, displacement) in displaced.joints.iter_mut().zip(&mode.shape) {
+            joint.location += displacement * scale;
+        }
+        self.render(&displaced, world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+
+    #[test]
+    fn stretched_interval_rings_at_its_axial_frequency() {
+        let mut world = World::new();
+        world.set_push_and_pull(true);
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 5.0, 0.0);
+        let omega = fabric.create_joint(2.0, 5.0, 0.0);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.stage = Stage::Pretenst;
+        let modes = fabric.modes(&world, 6);
+        let mass = fabric.joints[0].interval_mass;
+        let axial = fabric.intervals[0].axial_stiffness(&world, Stage::Pretenst, 1.0);
+        // the tension over the length across it is half the axial stiffness at double the rest length
+        let expected = [0.0, 0.0, 0.0, axial / mass, axial / mass, 2.0 * axial / mass];
+        for (mode, expected) in modes.iter().zip(&expected) {
+            // lengths come from a fast inverse square root, good to a fraction of a percent
+            assert!((mode.eigenvalue - expected).abs() < 1e-2 * axial / mass, "{:?} instead of {}", mode, expected);
+        }
+        let stretch = &modes[5].shape;
+        assert!((stretch[0].x.abs() - 1.0).abs() < 1e-3 && (stretch[0].x + stretch[1].x).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use std::fmt::{Display, Formatter};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::Real;
+
+/// Bumped whenever a saved `Fabric`, `World` or `FabricPlan` would no longer read back the same.
+pub const FORMAT_VERSION: u32 = 9;
+
+/// Bytes in each `Real`, which the `f64` feature doubles, so a save says which build wrote it.
+pub const PRECISION: u8 = std::mem::size_of::<Real>() as u8;
+
+#[derive(Debug)]
+pub enum PersistError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    Version { found: u32 },
+    Precision { found: u8 },
+}
+
+impl Display for PersistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PersistError::Json(error) => write!(f, "bad json: {error}"),
+            PersistError::Binary(error) => write!(f, "bad binary: {error}"),
+            PersistError::Version { found } => write!(f, "format version {found}, expected {FORMAT_VERSION}"),
+            PersistError::Precision { found } => write!(f, "saved with {found}-byte reals, this build uses {PRECISION}"),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u32,
+    precision: u8,
+    body: &'a T,
+}
+
+#[derive(Deserialize)]
+struct OwnedEnvelope<T> {
+    #[allow(dead_code)] // checked beforehand, but bincode still has to read past it
+    version: u32,
+    #[allow(dead_code)]
+    precision: u8,
+    body: T,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+    precision: u8,
+}
+
+pub fn to_json<T: Serialize>(value: &T) -> Result<String, PersistError> {
+    serde_json::to_string(&Envelope { version: FORMAT_VERSION, precision: PRECISION, body: value })
+        .map_err(PersistError::Json)
+}
+
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, PersistError> {
+    check_header(serde_json::from_str(json).map_err(PersistError::Json)?)?;
+    let envelope: OwnedEnvelope<T> = serde_json::from_str(json).map_err(PersistError::Json)?;
+    Ok(envelope.body)
+}
+
+pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>, PersistError> {
+    bincode::serialize(&Envelope { version: FORMAT_VERSION, precision: PRECISION, body: value })
+        .map_err(PersistError::Binary)
+}
+
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, PersistError> {
+    check_header(bincode::deserialize(bytes).map_err(PersistError::Binary)?)?;
+    let envelope: OwnedEnvelope<T> = bincode::deserialize(bytes).map_err(PersistError::Binary)?;
+    Ok(envelope.body)
+}
+
+fn check_header(Header { version, precision }: Header) -> Result<(), PersistError> {
+    if version != FORMAT_VERSION {
+        return Err(PersistError::Version { found: version });
+    }
+    if precision != PRECISION {
+        return Err(PersistError::Precision { found: precision });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::Builder;
+    use crate::fabric::Fabric;
+    use crate::tenscript::{parse, FabricPlan};
+    use crate::world::World;
+
+    fn grown() -> (FabricPlan, Fabric, World) {
+        let plan = parse("(fabric (build (seed :left) (grow A+ 2) (vulcanize :bowtie)))").unwrap();
+        let mut world = World::from_plan(&plan).unwrap();
+        let mut fabric = Fabric::new(100);
+        let mut builder = Builder::new(plan.clone(), &world).unwrap();
+        for _ in 0..200 {
+            builder.iterate(&mut fabric, &mut world);
+        }
+        (plan, fabric, world)
+    }
+
+    fn same_fabric(a: &Fabric, b: &Fabric) -> bool {
+        a.age == b.age &&
+            a.stage == b.stage &&
+            a.joints.len() == b.joints.len() &&
+            a.intervals.len() == b.intervals.len() &&
+            a.faces.len() == b.faces.len() &&
+            a.joints.iter().zip(&b.joints).all(|(a, b)| a.location == b.location && a.velocity == b.velocity) &&
+            a.intervals.iter().zip(&b.intervals).all(|(a, b)| a.length_nuance == b.length_nuance && a.attack == b.attack)
+    }
+
+    #[test]
+    fn round_trips() {
+        let (plan, fabric, world) = grown();
+        let json_fabric: Fabric = from_json(&to_json(&fabric).unwrap()).unwrap();
+        let bytes_fabric: Fabric = from_bytes(&to_bytes(&fabric).unwrap()).unwrap();
+        assert!(same_fabric(&fabric, &json_fabric));
+        assert!(same_fabric(&fabric, &bytes_fabric));
+        let json_world: World = from_json(&to_json(&world).unwrap()).unwrap();
+        assert_eq!(json_world.interval_countdown, world.interval_countdown);
+        let bytes_plan: FabricPlan = from_bytes(&to_bytes(&plan).unwrap()).unwrap();
+        assert_eq!(bytes_plan, plan);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let json = to_json(&World::new()).unwrap().replacen(&format!("\"version\":{FORMAT_VERSION}"), "\"version\":999", 1);
+        assert!(matches!(from_json::<World>(&json), Err(PersistError::Version { found: 999 })));
+    }
+
+    #[test]
+    fn rejects_other_precisions() {
+        let json = to_json(&World::new()).unwrap().replacen(&format!("\"precision\":{PRECISION}"), "\"precision\":2", 1);
+        assert!(matches!(from_json::<World>(&json), Err(PersistError::Precision { found: 2 })));
+        let mut bytes = to_bytes(&World::new()).unwrap();
+        bytes[4] = 2;
+        assert!(matches!(from_bytes::<World>(&bytes), Err(PersistError::Precision { found: 2 })));
+    }
+
+    #[cfg(feature = "f64")]
+    #[test]
+    fn keeps_double_precision() {
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 1.0, 0.0);
+        let omega = fabric.create_joint(0.1, 1.2, 0.3);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.accumulate_forces(&World::new(), 1.0);
+        // the fast inverse square root of the f32 build is off by around 1e-3
+        let unit = fabric.intervals[0].unit.magnitude();
+        assert!((unit - 1.0).abs() < 1e-15, "unit vector of length {}", unit);
+        assert_ne!(fabric.joints[omega].location.x as f32 as Real, fabric.joints[omega].location.x);
+        let json_fabric: Fabric = from_json(&to_json(&fabric).unwrap()).unwrap();
+        let bytes_fabric: Fabric = from_bytes(&to_bytes(&fabric).unwrap()).unwrap();
+        assert!(same_fabric(&fabric, &json_fabric));
+        assert!(same_fabric(&fabric, &bytes_fabric));
+    }
+}
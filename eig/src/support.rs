@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) 2020. Beautiful Code BV, Rotterdam, Netherlands
+ * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
+ */
+
+use nalgebra::*;
+
+use crate::constants::real_consts::TAU;
+use crate::constants::Real;
+use crate::fabric::Fabric;
+
+/// How a point load changes with the age of the fabric.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadCurve {
+    Constant,
+    /// From nothing to the full load over this many ticks, then steady.
+    Ramp { ticks: u32 },
+    /// Swinging between the full load and its opposite, with a period in ticks.
+    Sine { period: Real },
+}
+
+impl LoadCurve {
+    pub fn factor(&self, age: u32) -> Real {
+        match *self {
+            LoadCurve::Constant => 1.0,
+            LoadCurve::Ramp { ticks } if age >= ticks => 1.0,
+            LoadCurve::Ramp { ticks } => age as Real / ticks as Real,
+            LoadCurve::Sine { period } => (TAU * age as Real / period).sin(),
+        }
+    }
+}
+
+/// An outside force on a joint, like wind or a hand pushing.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PointLoad {
+    pub joint: usize,
+    pub force: Vector3<Real>,
+    pub curve: LoadCurve,
+}
+
+impl PointLoad {
+    pub fn force_at(&self, age: u32) -> Vector3<Real> {
+        self.force * self.curve.factor(age)
+    }
+
+    pub(crate) fn joint_removed(&mut self, index: usize) {
+        if self.joint > index {
+            self.joint -= 1;
+        }
+    }
+}
+
+impl Fabric {
+    /// Hold a joint still along the chosen x, y and z axes. A fabric with pinned joints is no
+    /// longer lifted to sit on the surface, since its supports decide where it is.
+    pub fn pin_joint(&mut self, joint: usize, axes: [bool; 3]) {
+        let joint = &mut self.joints[joint];
+        joint.pinned = axes;
+        joint.velocity = joint.free(joint.velocity);
+    }
+
+    pub fn unpin_joint(&mut self, joint: usize) {
+        let joint = &mut self.joints[joint];
+        joint.pinned = [false; 3];
+        joint.reaction = zero();
+    }
+
+    /// Hang a mass on a joint, which gravity pulls on along with the intervals' own mass.
+    pub fn attach_mass(&mut self, joint: usize, mass: Real) {
+        self.joints[joint].attached_mass = mass;
+    }
+
+    /// Returns the index of the load, for removing it later.
+    pub fn add_load(&mut self, joint: usize, force: Vector3<Real>, curve: LoadCurve) -> usize {
+        self.loads.push(PointLoad { joint, force, curve });
+        self.loads.len() - 1
+    }
+
+    pub fn remove_load(&mut self, index: usize) -> PointLoad {
+        self.loads.remove(index)
+    }
+
+    pub fn clear_loads(&mut self) {
+        self.loads.clear();
+    }
+
+    pub fn loads(&self) -> &[PointLoad] {
+        &self.loads
+    }
+
+    /// The force each support pushed with in the last tick, for every pinned joint. Summed over
+    /// the supports, it balances gravity and the loads once the fabric has settled.
+    pub fn reactions(&self) -> Vec<(usize, Vector3<Real>)> {
+        self.joints
+            .iter()
+            .enumerate()
+            .filter(|(_, joint)| joint.is_pinned())
+            .map(|(index, joint)| (index, joint.reaction))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+    use crate::world::World;
+
+    #[test]
+    fn load_curves() {
+        assert_eq!(LoadCurve::Constant.factor(7), 1.0);
+        assert_eq!(LoadCurve::Ramp { ticks: 10 }.factor(5), 0.5);
+        assert_eq!(LoadCurve::Ramp { ticks: 10 }.factor(50), 1.0);
+        assert!((LoadCurve::Sine { period: 8.0 }.factor(2) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn hanging_mass_is_carried_by_the_pin() {
+        let mut world = World::new();
+        world.set_float_value(WorldFeature::Drag, 0.01);
+        let mut fabric = Fabric::new(2);
+        let top = fabric.create_joint(0.0, 5.0, 0.0);
+        let bottom = fabric.create_joint(0.0, 4.0, 0.0);
+        fabric.create_interval(top, bottom, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.pin_joint(top, [true; 3]);
+        fabric.attach_mass(bottom, 0.5);
+        fabric.stage = Stage::Pretenst;
+        for _ in 0..100 {
+            fabric.iterate(&world);
+        }
+        assert_eq!(fabric.joints[top].location, Point3::new(0.0, 5.0, 0.0));
+        let weight: Real = fabric.joints.iter().map(|joint| joint.interval_mass).sum::<Real>() * world.gravity;
+        let reactions = fabric.reactions();
+        assert_eq!(reactions.len(), 1);
+        let (joint, reaction) = reactions[0];
+        assert_eq!(joint, top);
+        assert!((reaction.y - weight).abs() < weight * 1e-2, "{} instead of {}", reaction.y, weight);
+        assert!(reaction.x.abs() < weight * 1e-3 && reaction.z.abs() < weight * 1e-3);
+    }
+
+    #[test]
+    fn equilibrium_holds_pins_against_a_load() {
+        let world = World::new();
+        let mut fabric = Fabric::new(2);
+        let alpha = fabric.create_joint(0.0, 1.0, 0.0);
+        let omega = fabric.create_joint(1.001, 1.0, 0.0);
+        fabric.create_interval(alpha, omega, false, 1.0, 1.0, 1.0, 0.0);
+        fabric.pin_joint(alpha, [true; 3]);
+        fabric.pin_joint(omega, [false, true, true]);
+        let tension = fabric.intervals[0].stiffness * 0.01;
+        fabric.add_load(omega, Vector3::new(tension, 0.0, 0.0), LoadCurve::Constant);
+        fabric.stage = Stage::Pretenst;
+        fabric.solve_equilibrium(&world, 50, 1e-4).unwrap();
+        assert_eq!(fabric.joints[alpha].location, Point3::new(0.0, 1.0, 0.0));
+        assert!(fabric.joints[omega].location.x > 1.0);
+        assert_eq!(fabric.joints[omega].location.y, 1.0);
+    }
+}
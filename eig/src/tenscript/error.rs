@@ -1,18 +1,113 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::fmt::{Display, Formatter};
 
 use crate::tenscript::{parser, scanner, sexp};
+use crate::tenscript::scanner::Span;
 
 #[derive(Debug)]
-pub enum Error {
+#[allow(clippy::enum_variant_names)]
+pub enum ErrorKind {
     ScanError(scanner::ScanError),
     SexpParseError(sexp::ParseError),
     ParseError(parser::ParseError),
 }
 
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::ScanError(error) => Display::fmt(&error.kind, f),
+            ErrorKind::SexpParseError(error) => Display::fmt(&error.kind, f),
+            ErrorKind::ParseError(error) => Display::fmt(&error.kind, f),
+        }
+    }
+}
+
+/// A tenscript error which, once it knows the source, renders like a compiler diagnostic:
+///
+/// ```text
+/// error: build phase: expected (scale <percent>), found (scale 90)
+///  --> 4:5
+///   |
+/// 4 |     (scale 90)
+///   |     ^^^^^^^^^^
+/// ```
+#[derive(Debug)]
+pub struct Error {
+    pub kind: Box<ErrorKind>,
+    source_line: Option<String>,
+}
+
+impl Error {
+    /// Where in the source the error was found, if it can be pinned down.
+    pub fn span(&self) -> Option<Span> {
+        match self.kind.as_ref() {
+            ErrorKind::ScanError(error) => Some(error.span),
+            ErrorKind::SexpParseError(error) => Some(error.token.span),
+            ErrorKind::ParseError(error) => error.kind.span(),
+        }
+    }
+
+    pub(crate) fn with_source(mut self, source: &str) -> Self {
+        self.source_line = self.span()
+            .and_then(|span| source.lines().nth(span.start.line - 1))
+            .map(str::to_string);
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error { kind: Box::new(kind), source_line: None }
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(self, f)
+        write!(f, "error: {}", self.kind)?;
+        let Some(span) = self.span() else {
+            return Ok(());
+        };
+        write!(f, "\n --> {span}")?;
+        let Some(line) = &self.source_line else {
+            return Ok(());
+        };
+        let number = span.start.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let indent: String = line
+            .chars()
+            .take(span.start.col - 1)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let line_length = line.chars().count() + 1;
+        let end_col = if span.end.line == span.start.line { span.end.col } else { line_length };
+        let carets = "^".repeat(end_col.saturating_sub(span.start.col).max(1));
+        write!(f, "\n{gutter} |\n{number} | {line}\n{gutter} | {indent}{carets}")
     }
 }
 
 impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use crate::tenscript::parse;
+
+    #[test]
+    fn quotes_source_with_caret() {
+        let source = "(fabric\n  (build\n    (seed :left)\n    (scale 90)))";
+        let err = parse(source).unwrap_err();
+        let expected = "\
+error: build phase: expected (scale <percent>), found (scale 90)
+ --> 4:5
+  |
+4 |     (scale 90)))
+  |     ^^^^^^^^^^";
+        assert_eq!(err.to_string(), expected);
+    }
+
+    #[test]
+    fn points_at_scan_errors() {
+        let err = parse("(fabric\n  (name \"open))").unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!((span.start.line, span.start.col), (2, 9));
+        assert!(err.to_string().starts_with("error: unterminated string\n --> 2:9"));
+    }
+}
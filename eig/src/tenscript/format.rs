@@ -0,0 +1,313 @@
+use std::fmt::Write;
+
+use crate::tenscript::output::{BuildPhase, Chirality, FabricPlan, Features, PretensePhase, PretenseStep, SeedType, ShapeOperation, ShapePhase, SurfaceCharacter, TenscriptNode, VulcanizeType};
+use crate::tenscript::scanner::Span;
+use crate::tenscript::sexp::Sexp;
+
+/// Write a plan as canonical tenscript, which parses back into an equal plan.
+///
+/// Only a grow without any forward steps, which the builder refuses anyway,
+/// has no spelling, and it is written as a grow of a single twist.
+pub fn format(plan: &FabricPlan) -> String {
+    let mut out = String::new();
+    write_sexp(&mut out, &fabric_plan(plan), 0);
+    out.push('\n');
+    out
+}
+
+fn write_sexp(out: &mut String, sexp: &Sexp, indent: usize) {
+    let Sexp::List(terms, _) = sexp else {
+        write!(out, "{sexp}").unwrap();
+        return;
+    };
+    let inline = terms
+        .iter()
+        .position(|term| matches!(term, Sexp::List(..)))
+        .unwrap_or(terms.len());
+    out.push('(');
+    for (index, term) in terms[..inline].iter().enumerate() {
+        if index > 0 {
+            out.push(' ');
+        }
+        write!(out, "{term}").unwrap();
+    }
+    for term in &terms[inline..] {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent + 2));
+        write_sexp(out, term, indent + 2);
+    }
+    out.push(')');
+}
+
+fn fabric_plan(plan: &FabricPlan) -> Sexp {
+    let FabricPlan { name, scale, surface, features: fabric_features, build_phase, shape_phase, pretense_phase } = plan;
+    let mut terms = vec![ident("fabric")];
+    if let Some(name) = name {
+        terms.push(call("name", vec![Sexp::String(name.clone(), Span::default())]));
+    }
+    if let Some(scale) = scale {
+        terms.push(call("scale", vec![factor(*scale)]));
+    }
+    if let Some(surface) = surface {
+        let surface = match surface {
+            SurfaceCharacter::Frozen => "frozen",
+            SurfaceCharacter::Bouncy => "bouncy",
+            SurfaceCharacter::Sticky => "sticky",
+        };
+        terms.push(call("surface", vec![atom(surface)]));
+    }
+    terms.extend(features(fabric_features));
+    terms.push(build(build_phase));
+    terms.extend(shape(shape_phase));
+    terms.extend(pretense_phase.as_ref().map(pretense));
+    Sexp::List(terms, Span::default())
+}
+
+fn build(BuildPhase { seed, scale, vulcanize, growth }: &BuildPhase) -> Sexp {
+    let mut terms = vec![ident("build")];
+    if let Some(seed) = seed {
+        let seed = match seed {
+            SeedType::Left => "left",
+            SeedType::LeftRight => "left-right",
+            SeedType::Right => "right",
+            SeedType::RightLeft => "right-left",
+        };
+        terms.push(call("seed", vec![atom(seed)]));
+    }
+    if let Some(scale) = scale {
+        terms.push(call("scale", vec![Sexp::Percent(*scale, Span::default())]));
+    }
+    if let Some(vulcanize) = vulcanize {
+        let vulcanize = match vulcanize {
+            VulcanizeType::Bowtie => "bowtie",
+            VulcanizeType::Snelson => "snelson",
+        };
+        terms.push(call("vulcanize", vec![atom(vulcanize)]));
+    }
+    terms.extend(growth.as_ref().map(tenscript_node));
+    Sexp::List(terms, Span::default())
+}
+
+fn tenscript_node(node: &TenscriptNode) -> Sexp {
+    match node {
+        TenscriptNode::Grow { face, forward, scale, branch, marks } => {
+            let mut terms = vec![ident("grow"), atom(&face.to_string())];
+            let explicit = forward.iter().any(|chirality| *chirality != Chirality::Alternate);
+            if !forward.is_empty() && !explicit {
+                terms.push(Sexp::Integer(forward.len() as i64, Span::default()));
+            }
+            if let Some(scale) = scale {
+                terms.push(call("scale", vec![factor(*scale)]));
+            }
+            if explicit {
+                let steps = forward
+                    .iter()
+                    .map(|chirality| match chirality {
+                        Chirality::Left => Sexp::Integer(0, Span::default()),
+                        Chirality::Right => Sexp::Integer(1, Span::default()),
+                        Chirality::Alternate => atom("alternate"),
+                    })
+                    .collect();
+                terms.push(call("twist", steps));
+            }
+            for mark in marks {
+                terms.push(call("mark", vec![atom(&mark.face.to_string()), atom(&mark.name)]));
+            }
+            terms.extend(branch.as_deref().map(tenscript_node));
+            Sexp::List(terms, Span::default())
+        }
+        TenscriptNode::Branch { subtrees } => {
+            call("branch", subtrees.iter().map(tenscript_node).collect())
+        }
+    }
+}
+
+fn shape(ShapePhase { operations }: &ShapePhase) -> Option<Sexp> {
+    if operations.is_empty() {
+        return None;
+    }
+    let operations = operations
+        .iter()
+        .map(|operation| match operation {
+            ShapeOperation::PullTogether { mark_name, percent: None } =>
+                call("pull-together", vec![atom(mark_name)]),
+            ShapeOperation::PullTogether { mark_name, percent: Some(percent) } =>
+                call("pull-together", vec![atom(mark_name), Sexp::Percent(*percent, Span::default())]),
+            ShapeOperation::Distance { mark_name, percent } =>
+                call("distance", vec![atom(mark_name), Sexp::Percent(*percent, Span::default())]),
+            ShapeOperation::Join { mark_name } =>
+                call("join", vec![atom(mark_name)]),
+        })
+        .collect();
+    Some(call("shape", operations))
+}
+
+fn pretense(PretensePhase { steps, features: pretense_features }: &PretensePhase) -> Sexp {
+    let mut terms = vec![ident("pretense")];
+    terms.extend(features(pretense_features));
+    terms.extend(steps.iter().map(|step| match step {
+        PretenseStep::Wait { iterations } =>
+            call("wait", vec![Sexp::Integer(*iterations as i64, Span::default())]),
+        PretenseStep::ContractConflicts =>
+            call("contract-conflicts", vec![]),
+        PretenseStep::Orient { mark_name } =>
+            call("orient", vec![atom(mark_name)]),
+    }));
+    Sexp::List(terms, Span::default())
+}
+
+fn features(features: &Features) -> Option<Sexp> {
+    let Features {
+        iterations_per_frame,
+        visual_strain,
+        gravity,
+        pretenst_factor,
+        stiffness_factor,
+        push_over_pull,
+        drag,
+        shaping_pretenst_factor,
+        shaping_drag,
+        shaping_stiffness_factor,
+        antigravity,
+        interval_countdown,
+        pretensing_countdown,
+    } = features;
+    let mut terms = Vec::new();
+    if let Some(iterations) = iterations_per_frame {
+        terms.push(call("iterations-per-frame", vec![Sexp::Integer(*iterations as i64, Span::default())]));
+    }
+    let percents = [
+        ("visual-strain", visual_strain),
+        ("gravity", gravity),
+        ("pretenst-factor", pretenst_factor),
+        ("stiffness-factor", stiffness_factor),
+        ("push-over-pull", push_over_pull),
+        ("drag", drag),
+        ("shaping-pretenst-factor", shaping_pretenst_factor),
+        ("shaping-drag", shaping_drag),
+        ("shaping-stiffness-factor", shaping_stiffness_factor),
+        ("antigravity", antigravity),
+        ("interval-countdown", interval_countdown),
+        ("pretensing-countdown", pretensing_countdown),
+    ];
+    for (name, value) in percents {
+        if let Some(value) = value {
+            terms.push(call(name, vec![Sexp::Percent(*value, Span::default())]));
+        }
+    }
+    if terms.is_empty() {
+        return None;
+    }
+    Some(call("features", terms))
+}
+
+fn ident(name: &str) -> Sexp {
+    Sexp::Ident(name.to_string(), Span::default())
+}
+
+fn atom(name: &str) -> Sexp {
+    Sexp::Atom(name.to_string(), Span::default())
+}
+
+fn call(head: &str, tail: Vec<Sexp>) -> Sexp {
+    let mut terms = vec![ident(head)];
+    terms.extend(tail);
+    Sexp::List(terms, Span::default())
+}
+
+/// A factor written as the percent which the parser divides back into exactly the same factor.
+fn factor(factor: f64) -> Sexp {
+    let mut percent = factor * 100.0;
+    for _ in 0..4 {
+        if percent / 100.0 == factor {
+            break;
+        }
+        percent = if percent / 100.0 < factor { percent.next_up() } else { percent.next_down() };
+    }
+    Sexp::Percent(percent, Span::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tenscript::{format, parse, Chirality, FabricPlan, FaceName, TenscriptNode};
+
+    fn examples() -> Vec<&'static str> {
+        let source = include_str!("examples.ss");
+        let starts: Vec<usize> = source.match_indices("\n(fabric").map(|(index, _)| index + 1).collect();
+        starts
+            .iter()
+            .enumerate()
+            .map(|(index, &start)| &source[start..starts.get(index + 1).copied().unwrap_or(source.len())])
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_examples() {
+        for plan in examples().into_iter().map(|example| parse(example).unwrap()) {
+            let source = format(&plan);
+            let reparsed = parse(&source).unwrap_or_else(|err| panic!("{}\n{}", err, source));
+            assert_eq!(reparsed, plan, "{}", source);
+            assert_eq!(format(&reparsed), source);
+        }
+    }
+
+    #[test]
+    fn canonical_layout() {
+        let plan = parse("(fabric (name \"Say \\\"hi\\\"\") (features (gravity 50%)) (build (seed :left-right) (branch (grow A+ 3 (scale 92.5%) (mark A+ :end)) (grow B- (twist 0 1)))) (shape (pull-together :end)) (pretense (wait 100)))").unwrap();
+        let expected = "\
+(fabric
+  (name \"Say \\\"hi\\\"\")
+  (features
+    (gravity 50%))
+  (build
+    (seed :left-right)
+    (branch
+      (grow A+ 3
+        (scale 92.5%)
+        (mark A+ :end))
+      (grow B-
+        (twist 0 1))))
+  (shape
+    (pull-together :end))
+  (pretense
+    (wait 100)))
+";
+        assert_eq!(format(&plan), expected);
+    }
+
+    fn round_trip(plan: &FabricPlan) {
+        let source = format(plan);
+        assert_eq!(&parse(&source).unwrap_or_else(|err| panic!("{}\n{}", err, source)), plan, "{}", source);
+    }
+
+    #[test]
+    fn round_trips_mixed_twists() {
+        let mut plan = FabricPlan::default();
+        plan.build_phase.growth = Some(TenscriptNode::Grow {
+            face: FaceName::Aplus,
+            forward: vec![Chirality::Alternate, Chirality::Right, Chirality::Alternate, Chirality::Left],
+            scale: None,
+            marks: vec![],
+            branch: None,
+        });
+        round_trip(&plan);
+        assert!(format(&plan).contains("(twist :alternate 1 :alternate 0)"));
+    }
+
+    #[test]
+    fn round_trips_single_children() {
+        let grow = |face, branch: Option<TenscriptNode>| TenscriptNode::Grow {
+            face,
+            forward: vec![Chirality::Alternate; 2],
+            scale: None,
+            marks: vec![],
+            branch: branch.map(Box::new),
+        };
+        let mut plan = FabricPlan::default();
+        plan.build_phase.growth = Some(grow(FaceName::Aplus, Some(grow(FaceName::Bminus, None))));
+        round_trip(&plan);
+        let branch = TenscriptNode::Branch { subtrees: vec![grow(FaceName::Bminus, None)] };
+        plan.build_phase.growth = Some(grow(FaceName::Aplus, Some(branch)));
+        round_trip(&plan);
+    }
+}
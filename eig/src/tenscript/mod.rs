@@ -1,8 +1,12 @@
 mod error;
+mod format;
 mod parser;
 mod scanner;
 mod sexp;
 mod output;
 
-pub use parser::parse;
+pub use error::{Error, ErrorKind};
+pub use format::format;
+pub use scanner::{Location, Span};
+pub use parser::{parse, parse_all};
 pub use output::*;
\ No newline at end of file
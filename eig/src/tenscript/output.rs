@@ -1,6 +1,9 @@
 pub use std::fmt::{Display, Formatter};
 
+pub use crate::constants::SurfaceCharacter;
+
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FaceName {
     Seed,
     Aplus,
@@ -29,21 +32,17 @@ impl Display for FaceName {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VulcanizeType {
     Bowtie,
     Snelson,
 }
 
 
-#[derive(Debug, Clone, Copy)]
-pub enum SurfaceCharacter {
-    Frozen,
-    Bouncy,
-    Sticky,
-}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SeedType {
     Left,
     LeftRight,
@@ -51,17 +50,29 @@ pub enum SeedType {
     RightLeft,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mark {
     pub face: FaceName,
     pub name: String,
 }
 
-#[derive(Debug, Clone)]
+/// Which way one twist grown forward turns: alternating with its base, or explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Chirality {
+    Alternate,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TenscriptNode {
     Grow {
         face: FaceName,
-        forward: String,
+        forward: Vec<Chirality>,
+        scale: Option<f64>,
         branch: Option<Box<TenscriptNode>>,
         marks: Vec<Mark>,
     },
@@ -70,7 +81,8 @@ pub enum TenscriptNode {
     },
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BuildPhase {
     pub seed: Option<SeedType>,
     pub scale: Option<f64>,
@@ -78,7 +90,49 @@ pub struct BuildPhase {
     pub growth: Option<TenscriptNode>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShapeOperation {
+    PullTogether {
+        mark_name: String,
+        percent: Option<f64>,
+    },
+    Distance {
+        mark_name: String,
+        percent: f64,
+    },
+    Join {
+        mark_name: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShapePhase {
+    pub operations: Vec<ShapeOperation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PretenseStep {
+    Wait {
+        iterations: u32,
+    },
+    ContractConflicts,
+    Orient {
+        mark_name: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PretensePhase {
+    pub steps: Vec<PretenseStep>,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Features {
     pub iterations_per_frame: Option<u32>,
     pub visual_strain: Option<f64>,
@@ -95,11 +149,14 @@ pub struct Features {
     pub pretensing_countdown: Option<f64>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FabricPlan {
     pub name: Option<String>,
     pub scale: Option<f64>,
     pub surface: Option<SurfaceCharacter>,
     pub features: Features,
     pub build_phase: BuildPhase,
+    pub shape_phase: ShapePhase,
+    pub pretense_phase: Option<PretensePhase>,
 }
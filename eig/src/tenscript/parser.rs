@@ -1,16 +1,17 @@
-use std::collections::HashSet;
-use std::fmt::{Debug, Display, Formatter};
-use std::iter::repeat;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
 
-use crate::tenscript::error::Error;
-use crate::tenscript::output::{FabricPlan, FaceName, Mark, SeedType, SurfaceCharacter, TenscriptNode, VulcanizeType};
-use crate::tenscript::parser::ErrorKind::{AlreadyDefined, BadCall, IllegalCall, IllegalRepetition, Mismatch, MultipleBranches, Unknown};
+use crate::tenscript::error::{Error, ErrorKind as TenscriptErrorKind};
+use crate::tenscript::output::{Chirality, FabricPlan, FaceName, Features, Mark, PretensePhase, PretenseStep, SeedType, ShapeOperation, SurfaceCharacter, TenscriptNode, VulcanizeType};
+use crate::tenscript::parser::ErrorKind::{AlreadyDefined, BadCall, IllegalCall, IllegalRepetition, Mismatch, MultipleBranches, RecursionLimit, Reserved, TypeError, Undefined, Unknown, WrongArity};
+use crate::tenscript::scanner::Span;
 use crate::tenscript::sexp;
 use crate::tenscript::sexp::Sexp;
 
 #[derive(Debug, Clone)]
 pub struct ParseError {
-    kind: ErrorKind,
+    pub(crate) kind: ErrorKind,
 }
 
 impl Display for ParseError {
@@ -25,29 +26,77 @@ pub enum ErrorKind {
     BadCall { context: &'static str, expected: &'static str, sexp: Sexp },
     TypeError { expected: &'static str, sexp: Sexp },
     AlreadyDefined { property: &'static str, sexp: Sexp },
-    IllegalRepetition { kind: &'static str, value: String },
-    MultipleBranches,
+    IllegalRepetition { kind: &'static str, value: String, sexp: Sexp },
+    MultipleBranches { sexp: Sexp },
     IllegalCall { context: &'static str, sexp: Sexp },
+    Undefined { name: String, sexp: Sexp },
+    WrongArity { name: String, expected: usize, sexp: Sexp },
+    RecursionLimit { name: String, sexp: Sexp },
+    Reserved { name: String, sexp: Sexp },
     Unknown,
 }
 
 impl Display for ErrorKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(self, f)
+        match self {
+            Mismatch { rule, sexp, expected } => write!(f, "{rule}: expected {expected}, found {sexp}"),
+            BadCall { context, expected, sexp } => write!(f, "{context}: expected {expected}, found {sexp}"),
+            TypeError { expected, sexp } => write!(f, "expected {expected}, found {sexp}"),
+            AlreadyDefined { property, sexp } => write!(f, "{property} already defined: {sexp}"),
+            IllegalRepetition { kind, value, .. } => write!(f, "repeated {kind}: {value}"),
+            MultipleBranches { .. } => write!(f, "multiple branches"),
+            IllegalCall { context, sexp } => write!(f, "{context}: illegal call {sexp}"),
+            Undefined { name, .. } => write!(f, "no definition for ({name} ..)"),
+            WrongArity { name, expected, sexp } => write!(f, "({name} ..) takes {expected} argument(s), found {sexp}"),
+            RecursionLimit { name, .. } => write!(f, "({name} ..) expands more than {MAX_EXPANSION_DEPTH} levels deep"),
+            Reserved { name, .. } => write!(f, "({name} ..) is built in and cannot be defined"),
+            Unknown => write!(f, "unknown error"),
+        }
+    }
+}
+
+impl ErrorKind {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Mismatch { sexp, .. } |
+            BadCall { sexp, .. } |
+            TypeError { sexp, .. } |
+            AlreadyDefined { sexp, .. } |
+            IllegalRepetition { sexp, .. } |
+            MultipleBranches { sexp } |
+            IllegalCall { sexp, .. } |
+            Undefined { sexp, .. } |
+            WrongArity { sexp, .. } |
+            RecursionLimit { sexp, .. } |
+            Reserved { sexp, .. } => Some(sexp.span()),
+            Unknown => None,
+        }
     }
 }
 
 pub fn parse(source: &str) -> Result<FabricPlan, Error> {
-    let sexp = &sexp::parse(source)?;
+    let sexp = &sexp::parse(source)
+        .map_err(|err| err.with_source(source))?;
     fabric_plan(sexp)
-        .map_err(|kind| Error::ParseError(ParseError { kind }))
+        .map_err(|kind| Error::from(TenscriptErrorKind::ParseError(ParseError { kind })).with_source(source))
+}
+
+/// Parse a source holding any number of fabrics, one after the other.
+pub fn parse_all(source: &str) -> Result<Vec<FabricPlan>, Error> {
+    let sexps = sexp::parse_all(source)
+        .map_err(|err| err.with_source(source))?;
+    sexps
+        .iter()
+        .map(|sexp| fabric_plan(sexp)
+            .map_err(|kind| Error::from(TenscriptErrorKind::ParseError(ParseError { kind })).with_source(source)))
+        .collect()
 }
 
 macro_rules! expect_enum {
         ($value:expr, { $($name:pat => $enum_val:expr,)+ }) => {
             {
                 let expected = stringify!($($name)|+);
-                let $crate::tenscript::sexp::Sexp::Atom(ref name) = $value else {
+                let $crate::tenscript::sexp::Sexp::Atom(ref name, _) = $value else {
                     return Err($crate::tenscript::parser::ErrorKind::TypeError { expected, sexp: $value.clone() })
                 };
                 match name.as_str() {
@@ -60,6 +109,24 @@ macro_rules! expect_enum {
         }
     }
 
+/// How deeply definitions may call each other before we assume runaway recursion.
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+/// Names of the calls tenscript itself knows, which definitions may not take over.
+const BUILT_IN: &[&str] = &[
+    "fabric", "def", "name", "scale", "surface", "features", "build", "shape", "pretense",
+    "seed", "vulcanize", "branch", "grow", "mark", "twist",
+    "pull-together", "distance", "join", "wait", "contract-conflicts", "orient",
+    "iterations-per-frame", "visual-strain", "gravity", "pretenst-factor", "stiffness-factor",
+    "push-over-pull", "drag", "shaping-pretenst-factor", "shaping-drag", "shaping-stiffness-factor",
+    "antigravity", "interval-countdown", "pretensing-countdown",
+];
+
+struct Definition {
+    params: Vec<String>,
+    body: Sexp,
+}
+
 struct Call<'a> {
     head: &'a str,
     tail: &'a [Sexp],
@@ -67,13 +134,13 @@ struct Call<'a> {
 
 
 fn expect_call<'a>(rule: &'static str, sexp: &'a Sexp) -> Result<Call<'a>, ErrorKind> {
-    let Sexp::List(ref terms) = sexp else {
+    let Sexp::List(ref terms, _) = sexp else {
         return Err(Mismatch { rule, expected: "( .. )", sexp: sexp.clone() });
     };
     let [ref head, ref tail @ ..] = terms[..] else {
         return Err(Mismatch { rule, expected: "(<head> ..)", sexp: sexp.clone() });
     };
-    let Sexp::Ident(ref head) = head else {
+    let Sexp::Ident(ref head, _) = head else {
         return Err(Mismatch { rule, expected: "(<head:ident> ..)", sexp: sexp.clone() });
     };
     Ok(Call {
@@ -87,15 +154,25 @@ fn fabric_plan(sexp: &Sexp) -> Result<FabricPlan, ErrorKind> {
         return Err(Mismatch { rule: "fabric", expected: "(fabric ..)", sexp: sexp.clone() });
     };
 
+    let mut definitions = HashMap::new();
+    for sexp in tail {
+        if let Call { head: "def", tail } = expect_call("fabric", sexp)? {
+            definition(&mut definitions, sexp, tail)?;
+        }
+    }
     let mut fabric = FabricPlan::default();
     for sexp in tail {
+        if let Call { head: "def", .. } = expect_call("fabric", sexp)? {
+            continue;
+        }
+        let sexp = &expand(sexp, &definitions, 0)?;
         let Call { head, tail } = expect_call("fabric", sexp)?;
         match head {
             "scale" => {
                 if fabric.scale.is_some() {
                     return Err(AlreadyDefined { property: "scale", sexp: sexp.clone() });
                 };
-                let &[Sexp::Percent(scale)] = tail else {
+                let &[Sexp::Percent(scale, _)] = tail else {
                     return Err(BadCall { context: "fabric plan", expected: "(scale <percent>)", sexp: sexp.clone() });
                 };
                 fabric.scale = Some(scale / 100.0);
@@ -104,7 +181,7 @@ fn fabric_plan(sexp: &Sexp) -> Result<FabricPlan, ErrorKind> {
                 if fabric.surface.is_some() {
                     return Err(AlreadyDefined { property: "surface", sexp: sexp.clone() });
                 };
-                let &[ref value] = tail else {
+                let [value] = tail else {
                     return Err(BadCall { context: "fabric plan", expected: "(surface <value>)", sexp: sexp.clone() });
                 };
                 let surface = expect_enum!(value, {
@@ -118,34 +195,119 @@ fn fabric_plan(sexp: &Sexp) -> Result<FabricPlan, ErrorKind> {
                 if fabric.name.is_some() {
                     return Err(AlreadyDefined { property: "name", sexp: sexp.clone() });
                 };
-                let &[Sexp::String(ref name)] = tail else {
+                let &[Sexp::String(ref name, _)] = tail else {
                     return Err(BadCall { context: "fabric plan", expected: "(name <string>)", sexp: sexp.clone() });
                 };
                 fabric.name = Some(name.clone());
             }
             "features" => {
-                features(&mut fabric, tail)?;
+                features(&mut fabric.features, tail)?;
             }
             "build" => {
                 build(&mut fabric, tail)?;
             }
-            "shape" => { todo!() }
-            "pretense" => { todo!() }
+            "seed" | "vulcanize" | "branch" | "grow" => {
+                // the build phase may be written out directly in the fabric
+                build(&mut fabric, std::slice::from_ref(sexp))?;
+            }
+            "shape" => {
+                shape(&mut fabric, tail)?;
+            }
+            "pretense" => {
+                if fabric.pretense_phase.is_some() {
+                    return Err(AlreadyDefined { property: "pretense", sexp: sexp.clone() });
+                };
+                fabric.pretense_phase = Some(pretense(tail)?);
+            }
             _ => return Err(IllegalCall { context: "fabric plan", sexp: sexp.clone() })
         }
     }
     Ok(fabric)
 }
 
-fn build(FabricPlan { build_phase, .. }: &mut FabricPlan, sexps: &[Sexp]) -> Result<(), ErrorKind> {
+fn definition(definitions: &mut HashMap<String, Definition>, sexp: &Sexp, tail: &[Sexp]) -> Result<(), ErrorKind> {
+    let [Sexp::List(signature, _), body] = tail else {
+        return Err(BadCall { context: "definition", expected: "(def (<name> <param>*) <body>)", sexp: sexp.clone() });
+    };
+    let mut names = Vec::new();
+    for term in signature {
+        let Sexp::Ident(name, _) = term else {
+            return Err(TypeError { expected: "ident", sexp: term.clone() });
+        };
+        if names.contains(name) {
+            return Err(IllegalRepetition { kind: "parameter", value: name.clone(), sexp: term.clone() });
+        }
+        names.push(name.clone());
+    }
+    if names.is_empty() {
+        return Err(BadCall { context: "definition", expected: "(def (<name> <param>*) <body>)", sexp: sexp.clone() });
+    }
+    let name = names.remove(0);
+    if BUILT_IN.contains(&name.as_str()) {
+        return Err(Reserved { name, sexp: sexp.clone() });
+    }
+    if definitions.contains_key(&name) {
+        return Err(AlreadyDefined { property: "definition", sexp: sexp.clone() });
+    }
+    definitions.insert(name, Definition { params: names, body: body.clone() });
+    Ok(())
+}
+
+/// Replace calls to definitions with their bodies, arguments substituted for parameters.
+fn expand(sexp: &Sexp, definitions: &HashMap<String, Definition>, depth: usize) -> Result<Sexp, ErrorKind> {
+    let Sexp::List(terms, span) = sexp else {
+        return Ok(sexp.clone());
+    };
+    if let [Sexp::Ident(name, _), args @ ..] = &terms[..] {
+        if let Some(Definition { params, body }) = definitions.get(name) {
+            if depth >= MAX_EXPANSION_DEPTH {
+                return Err(RecursionLimit { name: name.clone(), sexp: sexp.clone() });
+            }
+            if args.len() != params.len() {
+                return Err(WrongArity { name: name.clone(), expected: params.len(), sexp: sexp.clone() });
+            }
+            let args = args
+                .iter()
+                .map(|arg| expand(arg, definitions, depth))
+                .collect::<Result<Vec<_>, _>>()?;
+            let bindings: HashMap<&str, &Sexp> = params.iter().map(String::as_str).zip(args.iter()).collect();
+            return expand(&substitute(body, &bindings), definitions, depth + 1);
+        }
+    }
+    let terms = terms
+        .iter()
+        .map(|term| expand(term, definitions, depth))
+        .collect::<Result<_, _>>()?;
+    Ok(Sexp::List(terms, *span))
+}
+
+fn substitute(sexp: &Sexp, bindings: &HashMap<&str, &Sexp>) -> Sexp {
+    match sexp {
+        Sexp::Ident(name, _) => bindings
+            .get(name.as_str())
+            .map_or_else(|| sexp.clone(), |&arg| arg.clone()),
+        Sexp::List(terms, span) => {
+            let (head, args) = match &terms[..] {
+                [head @ Sexp::Ident(..), args @ ..] => (Some(head.clone()), args),
+                _ => (None, &terms[..]),
+            };
+            let args = args.iter().map(|arg| substitute(arg, bindings));
+            Sexp::List(head.into_iter().chain(args).collect(), *span)
+        }
+        _ => sexp.clone(),
+    }
+}
+
+fn build(fabric: &mut FabricPlan, sexps: &[Sexp]) -> Result<(), ErrorKind> {
     for sexp in sexps {
+        let build_phase = &mut fabric.build_phase;
         let Call { head, tail } = expect_call("build", sexp)?;
         match head {
             "seed" => {
                 if build_phase.seed.is_some() {
                     return Err(AlreadyDefined { property: "seed", sexp: sexp.clone() });
                 };
-                let &[ref value] = tail else {
+                let [value] = tail else {
                     return Err(BadCall { context: "build phase", expected: "(seed <value>)", sexp: sexp.clone() });
                 };
                 let seed_type = expect_enum!(value, {
@@ -161,7 +323,7 @@ fn build(FabricPlan { build_phase, .. }: &mut FabricPlan, sexps: &[Sexp]) -> Res
                     return Err(AlreadyDefined { property: "vulcanize", sexp: sexp.clone() });
                 };
 
-                let &[ref value] = tail else {
+                let [value] = tail else {
                     return Err(BadCall { context: "build phase", expected: "(vulcanize <value>)", sexp: sexp.clone() });
                 };
                 let vulcanize_type = expect_enum!(value, {
@@ -174,7 +336,7 @@ fn build(FabricPlan { build_phase, .. }: &mut FabricPlan, sexps: &[Sexp]) -> Res
                 if build_phase.scale.is_some() {
                     return Err(AlreadyDefined { property: "scale", sexp: sexp.clone() });
                 };
-                let &[Sexp::Percent(value)] = tail else {
+                let &[Sexp::Percent(value, _)] = tail else {
                     return Err(BadCall { context: "build phase", expected: "(scale <percent>)", sexp: sexp.clone() });
                 };
                 build_phase.scale = Some(value);
@@ -185,51 +347,188 @@ fn build(FabricPlan { build_phase, .. }: &mut FabricPlan, sexps: &[Sexp]) -> Res
                 };
                 build_phase.growth = Some(tenscript_node(sexp)?);
             }
+            "pull-together" => {
+                // the marks are brought together once the build is done, as if written in the shape phase
+                shape(fabric, std::slice::from_ref(sexp))?;
+            }
             _ => return Err(IllegalCall { context: "build phase", sexp: sexp.clone() })
         }
     }
     Ok(())
 }
 
+fn shape(FabricPlan { shape_phase, .. }: &mut FabricPlan, sexps: &[Sexp]) -> Result<(), ErrorKind> {
+    for sexp in sexps {
+        let Call { head, tail } = expect_call("shape", sexp)?;
+        let operation = match head {
+            "pull-together" => {
+                match tail {
+                    [Sexp::Atom(mark_name, _)] => ShapeOperation::PullTogether {
+                        mark_name: mark_name.clone(),
+                        percent: None,
+                    },
+                    [Sexp::Atom(mark_name, _), Sexp::Percent(percent, _)] => ShapeOperation::PullTogether {
+                        mark_name: mark_name.clone(),
+                        percent: Some(*percent),
+                    },
+                    _ => return Err(BadCall { context: "shape phase", expected: "(pull-together <mark> <percent>?)", sexp: sexp.clone() }),
+                }
+            }
+            "distance" => {
+                let [Sexp::Atom(mark_name, _), Sexp::Percent(percent, _)] = tail else {
+                    return Err(BadCall { context: "shape phase", expected: "(distance <mark> <percent>)", sexp: sexp.clone() });
+                };
+                ShapeOperation::Distance {
+                    mark_name: mark_name.clone(),
+                    percent: *percent,
+                }
+            }
+            "join" => {
+                let [Sexp::Atom(mark_name, _)] = tail else {
+                    return Err(BadCall { context: "shape phase", expected: "(join <mark>)", sexp: sexp.clone() });
+                };
+                ShapeOperation::Join {
+                    mark_name: mark_name.clone(),
+                }
+            }
+            _ => return Err(IllegalCall { context: "shape phase", sexp: sexp.clone() })
+        };
+        shape_phase.operations.push(operation);
+    }
+    Ok(())
+}
+
+fn pretense(sexps: &[Sexp]) -> Result<PretensePhase, ErrorKind> {
+    let mut pretense_phase = PretensePhase::default();
+    for sexp in sexps {
+        let Call { head, tail } = expect_call("pretense", sexp)?;
+        let step = match head {
+            "wait" => {
+                let &[Sexp::Integer(iterations, _)] = tail else {
+                    return Err(BadCall { context: "pretense phase", expected: "(wait <integer>)", sexp: sexp.clone() });
+                };
+                if iterations < 0 {
+                    return Err(BadCall { context: "pretense phase", expected: "(wait <non-negative integer>)", sexp: sexp.clone() });
+                }
+                PretenseStep::Wait { iterations: iterations as u32 }
+            }
+            "contract-conflicts" => {
+                let [] = tail else {
+                    return Err(BadCall { context: "pretense phase", expected: "(contract-conflicts)", sexp: sexp.clone() });
+                };
+                PretenseStep::ContractConflicts
+            }
+            "orient" => {
+                let [Sexp::Atom(mark_name, _)] = tail else {
+                    return Err(BadCall { context: "pretense phase", expected: "(orient <mark>)", sexp: sexp.clone() });
+                };
+                PretenseStep::Orient { mark_name: mark_name.clone() }
+            }
+            "features" => {
+                features(&mut pretense_phase.features, tail)?;
+                continue;
+            }
+            _ => return Err(IllegalCall { context: "pretense phase", sexp: sexp.clone() })
+        };
+        pretense_phase.steps.push(step);
+    }
+    Ok(pretense_phase)
+}
+
 fn tenscript_node(sexp: &Sexp) -> Result<TenscriptNode, ErrorKind> {
     let Call { head, tail } = expect_call("tenscript_node", sexp)?;
     match head {
         "grow" => {
-            let &[
-            ref face_atom @ Sexp::Atom(ref face_name),
-            Sexp::Integer(forward_count),
-            ref post_growth @ ..,
-            ] = tail else {
-                return Err(Mismatch { rule: "tenscript_node", expected: "face name and forward count", sexp: sexp.clone() });
+            let [ref face_sexp, ref post_growth @ ..] = tail[..] else {
+                return Err(Mismatch { rule: "tenscript_node", expected: "face name", sexp: sexp.clone() });
+            };
+            let face = expect_face_name(face_sexp)?;
+            let (forward_count, post_growth) = match post_growth {
+                [Sexp::Integer(count, _), rest @ ..] if *count > 0 => (Some(*count as usize), rest),
+                [count @ Sexp::Integer(..), ..] =>
+                    return Err(TypeError { expected: "positive forward count", sexp: count.clone() }),
+                _ => (None, post_growth),
             };
-            let face = expect_face_name(face_atom, face_name)?;
-            let forward = repeat("X").take(forward_count as usize).collect();
+            let mut twist: Option<Vec<Chirality>> = None;
+            let mut scale = None;
             let mut marks = Vec::new();
             let mut branch = None;
+            let mut chain = Vec::new();
             for post_growth_op in post_growth {
                 let Call { head: op_head, tail: op_tail } = expect_call("tenscript_node", post_growth_op)?;
                 match op_head {
                     "mark" => {
-                        let [ face_sexp @ Sexp::Atom(face_name), Sexp::Atom(ref name) ] = op_tail else {
+                        let [ face_sexp, Sexp::Atom(ref name, _) ] = op_tail else {
                             return Err(Mismatch { rule: "tenscript_node", expected: "(mark <face_name> <name>)", sexp: post_growth_op.clone() });
                         };
 
-                        let face = expect_face_name(face_sexp, &face_name)?;
+                        let face = expect_face_name(face_sexp)?;
                         marks.push(Mark {
                             face,
                             name: name.clone(),
                         });
                     }
                     "branch" => {
-                        if branch.is_some() {
-                            return Err(MultipleBranches);
+                        if branch.is_some() || !chain.is_empty() {
+                            return Err(MultipleBranches { sexp: post_growth_op.clone() });
                         }
                         branch = Some(Box::new(tenscript_node(post_growth_op)?));
                     }
-                    _ => return Err(Mismatch { rule: "tenscript_node", expected: "mark | branch", sexp: sexp.clone() }),
+                    "grow" => {
+                        if branch.is_some() {
+                            return Err(MultipleBranches { sexp: post_growth_op.clone() });
+                        }
+                        chain.push((tenscript_node(post_growth_op)?, post_growth_op));
+                    }
+                    "scale" => {
+                        if scale.is_some() {
+                            return Err(AlreadyDefined { property: "scale", sexp: post_growth_op.clone() });
+                        }
+                        let &[Sexp::Percent(percent, _)] = op_tail else {
+                            return Err(BadCall { context: "grow", expected: "(scale <percent>)", sexp: post_growth_op.clone() });
+                        };
+                        scale = Some(percent / 100.0);
+                    }
+                    "twist" => {
+                        if twist.is_some() {
+                            return Err(AlreadyDefined { property: "twist", sexp: post_growth_op.clone() });
+                        }
+                        if op_tail.is_empty() {
+                            return Err(BadCall { context: "grow", expected: "(twist <0|1|:alternate>+)", sexp: post_growth_op.clone() });
+                        }
+                        let chiralities = op_tail
+                            .iter()
+                            .map(|step| match step {
+                                Sexp::Integer(0, _) => Ok(Chirality::Left),
+                                Sexp::Integer(1, _) => Ok(Chirality::Right),
+                                Sexp::Atom(name, _) if name == "alternate" => Ok(Chirality::Alternate),
+                                _ => Err(TypeError { expected: "0 (left) | 1 (right) | :alternate", sexp: step.clone() }),
+                            })
+                            .collect::<Result<_, _>>()?;
+                        twist = Some(chiralities);
+                    }
+                    _ => return Err(Undefined { name: op_head.to_string(), sexp: post_growth_op.clone() }),
+                }
+            }
+            let forward = match (forward_count, twist) {
+                (Some(count), Some(twist)) if count != twist.len() =>
+                    return Err(BadCall { context: "grow", expected: "one (twist ..) entry per forward step", sexp: sexp.clone() }),
+                (_, Some(twist)) => twist,
+                (Some(count), None) => vec![Chirality::Alternate; count],
+                (None, None) => vec![Chirality::Alternate],
+            };
+            // grows written one after another each grow from the end of the one before
+            for (link, link_sexp) in chain.into_iter().rev() {
+                let TenscriptNode::Grow { face, forward, scale, marks, branch: link_branch } = link else {
+                    return Err(Unknown);
+                };
+                if branch.is_some() && link_branch.is_some() {
+                    return Err(MultipleBranches { sexp: link_sexp.clone() });
                 }
+                let branch_of_link = branch.take().or(link_branch);
+                branch = Some(Box::new(TenscriptNode::Grow { face, forward, scale, marks, branch: branch_of_link }));
             }
-            Ok(TenscriptNode::Grow { face, forward, marks, branch })
+            Ok(TenscriptNode::Grow { face, forward, scale, marks, branch })
         }
         "branch" => {
             let mut subtrees = Vec::new();
@@ -243,7 +542,7 @@ fn tenscript_node(sexp: &Sexp) -> Result<TenscriptNode, ErrorKind> {
                     return Err(Unknown);
                 };
                 if face_exists.contains(&face) {
-                    return Err(IllegalRepetition { kind: "face name", value: face.to_string() });
+                    return Err(IllegalRepetition { kind: "face name", value: face.to_string(), sexp: sub_sexp.clone() });
                 }
                 face_exists.insert(face);
 
@@ -255,105 +554,114 @@ fn tenscript_node(sexp: &Sexp) -> Result<TenscriptNode, ErrorKind> {
     }
 }
 
-fn expect_face_name(sexp: &Sexp, face_name: &str) -> Result<FaceName, ErrorKind> {
-    Ok(match face_name {
-        "A+" => FaceName::Aplus,
-        "B+" => FaceName::Bplus,
-        "C+" => FaceName::Cplus,
-        "D+" => FaceName::Dplus,
-        "A-" => FaceName::Aminus,
-        "B-" => FaceName::Bminus,
-        "C-" => FaceName::Cminus,
-        "D-" => FaceName::Dminus,
-        _ => return Err(Mismatch { rule: "tenscript_node", expected: "unrecognized face name", sexp: sexp.clone() }),
+/// Face names are written A+ .. D-, or as a single letter the way the client writes them,
+/// with A .. D for the plus faces and a .. d for the minus faces.
+fn expect_face_name(sexp: &Sexp) -> Result<FaceName, ErrorKind> {
+    Ok(match sexp {
+        Sexp::Atom(name, _) if name == "A+" || name == "A" => FaceName::Aplus,
+        Sexp::Atom(name, _) if name == "B+" || name == "B" => FaceName::Bplus,
+        Sexp::Atom(name, _) if name == "C+" || name == "C" => FaceName::Cplus,
+        Sexp::Atom(name, _) if name == "D+" || name == "D" => FaceName::Dplus,
+        Sexp::Atom(name, _) if name == "A-" => FaceName::Aminus,
+        Sexp::Atom(name, _) if name == "B-" => FaceName::Bminus,
+        Sexp::Atom(name, _) if name == "C-" => FaceName::Cminus,
+        Sexp::Atom(name, _) if name == "D-" => FaceName::Dminus,
+        Sexp::Ident(name, _) if name == "a" => FaceName::Aminus,
+        Sexp::Ident(name, _) if name == "b" => FaceName::Bminus,
+        Sexp::Ident(name, _) if name == "c" => FaceName::Cminus,
+        Sexp::Ident(name, _) if name == "d" => FaceName::Dminus,
+        _ => return Err(Mismatch { rule: "tenscript_node", expected: "face name A+ .. D-", sexp: sexp.clone() }),
     })
 }
 
-fn features(FabricPlan { features, .. }: &mut FabricPlan, sexps: &[Sexp]) -> Result<(), ErrorKind> {
+fn features(features: &mut Features, sexps: &[Sexp]) -> Result<(), ErrorKind> {
     let mut feature_defined = HashSet::new();
     for sexp in sexps {
-        let Call { head: key, tail: &[ref val] } = expect_call("features", sexp)? else {
+        let Call { head: key, tail: [val] } = expect_call("features", sexp)? else {
             return Err(BadCall { context: "features", expected: "(<feature-name> <value>)", sexp: sexp.clone() });
         };
         if feature_defined.contains(key) {
-            return Err(IllegalRepetition { kind: "feature name", value: key.to_string() });
+            return Err(IllegalRepetition { kind: "feature name", value: key.to_string(), sexp: sexp.clone() });
         }
         feature_defined.insert(key.to_string());
         match key {
             "iterations-per-frame" => {
-                let Sexp::Integer(value) = val else {
-                    return Err(Mismatch { rule: "features", expected: "(iterations-per-frame <integer>)", sexp: sexp.clone() });
+                let Some(value) = (match val {
+                    Sexp::Integer(value, _) => u32::try_from(*value).ok(),
+                    _ => None,
+                }) else {
+                    return Err(Mismatch { rule: "features", expected: "(iterations-per-frame <non-negative integer>)", sexp: sexp.clone() });
                 };
-                features.iterations_per_frame = Some(*value as u32);
+                features.iterations_per_frame = Some(value);
             }
             "visual-strain" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(visual-strain <percent>)", sexp: sexp.clone() });
                 };
                 features.visual_strain = Some(*value);
             }
             "gravity" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(gravity <percent>)", sexp: sexp.clone() });
                 };
                 features.gravity = Some(*value);
             }
             "pretenst-factor" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(pretenst-factor <percent>)", sexp: sexp.clone() });
                 };
                 features.pretenst_factor = Some(*value);
             }
             "stiffness-factor" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(stiffness-factor <percent>)", sexp: sexp.clone() });
                 };
                 features.stiffness_factor = Some(*value);
             }
             "push-over-pull" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(push-over-pull <percent>)", sexp: sexp.clone() });
                 };
                 features.push_over_pull = Some(*value);
             }
             "drag" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(drag <percent>)", sexp: sexp.clone() });
                 };
                 features.drag = Some(*value);
             }
             "shaping-pretenst-factor" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(shaping-pretenst-factor <percent>)", sexp: sexp.clone() });
                 };
                 features.shaping_pretenst_factor = Some(*value);
             }
             "shaping-drag" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(shaping-drag <percent>)", sexp: sexp.clone() });
                 };
                 features.shaping_drag = Some(*value);
             }
             "shaping-stiffness-factor" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(shaping-stiffness-factor <percent>)", sexp: sexp.clone() });
                 };
                 features.shaping_stiffness_factor = Some(*value);
             }
             "antigravity" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(antigravity <percent>)", sexp: sexp.clone() });
                 };
                 features.antigravity = Some(*value);
             }
             "interval-countdown" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(interval-countdown <percent>)", sexp: sexp.clone() });
                 };
                 features.interval_countdown = Some(*value);
             }
             "pretensing-countdown" => {
-                let Sexp::Percent(value) = val else {
+                let Sexp::Percent(value, _) = val else {
                     return Err(Mismatch { rule: "features", expected: "(pretensing-countdown <percent>)", sexp: sexp.clone() });
                 };
                 features.pretensing_countdown = Some(*value);
@@ -364,3 +672,167 @@ fn features(FabricPlan { features, .. }: &mut FabricPlan, sexps: &[Sexp]) -> Res
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(source: &str) -> ErrorKind {
+        match *parse(source).unwrap_err().kind {
+            TenscriptErrorKind::ParseError(ParseError { kind }) => kind,
+            other => panic!("expected a parse error, got {}", other),
+        }
+    }
+
+    #[test]
+    fn shape_operations() {
+        let plan = parse("
+            (fabric
+              (build (grow A+ 1 (mark A+ :end)))
+              (shape
+                (pull-together :end)
+                (pull-together :end 5%)
+                (distance :end 60%)
+                (join :end)))").unwrap();
+        assert_eq!(plan.shape_phase.operations, vec![
+            ShapeOperation::PullTogether { mark_name: "end".to_string(), percent: None },
+            ShapeOperation::PullTogether { mark_name: "end".to_string(), percent: Some(5.0) },
+            ShapeOperation::Distance { mark_name: "end".to_string(), percent: 60.0 },
+            ShapeOperation::Join { mark_name: "end".to_string() },
+        ]);
+        assert!(parse("(fabric (shape))").unwrap().shape_phase.operations.is_empty());
+        for source in [
+            "(fabric (shape (pull-together)))",
+            "(fabric (shape (pull-together :end 5)))",
+            "(fabric (shape (distance :end)))",
+            "(fabric (shape (join end)))",
+        ] {
+            assert!(matches!(parse_err(source), BadCall { context: "shape phase", .. }), "{}", source);
+        }
+        assert!(matches!(parse_err("(fabric (shape (twist :end)))"), IllegalCall { context: "shape phase", .. }));
+        assert!(matches!(parse_err("(fabric (shape :end))"), Mismatch { rule: "shape", .. }));
+    }
+
+    #[test]
+    fn pretense_steps() {
+        let plan = parse("
+            (fabric
+              (build (grow A+ 1 (mark A+ :legs)))
+              (pretense
+                (wait 100)
+                (features (gravity 50%))
+                (contract-conflicts)
+                (orient :legs)
+                (wait 0)))").unwrap();
+        let pretense_phase = plan.pretense_phase.unwrap();
+        assert_eq!(pretense_phase.steps, vec![
+            PretenseStep::Wait { iterations: 100 },
+            PretenseStep::ContractConflicts,
+            PretenseStep::Orient { mark_name: "legs".to_string() },
+            PretenseStep::Wait { iterations: 0 },
+        ]);
+        assert_eq!(pretense_phase.features.gravity, Some(50.0));
+        assert_eq!(plan.features.gravity, None);
+        assert_eq!(parse("(fabric (pretense))").unwrap().pretense_phase, Some(PretensePhase::default()));
+        for source in [
+            "(fabric (pretense (wait -1)))",
+            "(fabric (pretense (wait 10%)))",
+            "(fabric (pretense (contract-conflicts :now)))",
+            "(fabric (pretense (orient)))",
+        ] {
+            assert!(matches!(parse_err(source), BadCall { context: "pretense phase", .. }), "{}", source);
+        }
+        assert!(matches!(parse_err("(fabric (pretense (shake)))"), IllegalCall { context: "pretense phase", .. }));
+        assert!(matches!(parse_err("(fabric (pretense) (pretense))"), AlreadyDefined { property: "pretense", .. }));
+    }
+
+    #[test]
+    fn expands_definitions() {
+        let plan = parse("
+            (fabric
+              (def (limb face count) (grow face count (mark A+ :tip)))
+              (def (pair count) (branch (limb A+ count) (limb A- count)))
+              (build (pair 3)))").unwrap();
+        let Some(TenscriptNode::Branch { subtrees }) = plan.build_phase.growth else {
+            panic!("expected a branch");
+        };
+        assert_eq!(subtrees.len(), 2);
+        assert!(matches!(&subtrees[0], TenscriptNode::Grow { face: FaceName::Aplus, forward, marks, .. }
+            if forward.len() == 3 && marks[0].name == "tip"));
+    }
+
+    #[test]
+    fn grow_modifiers() {
+        let plan = parse("
+            (fabric
+              (build
+                (grow A+
+                  (scale 95%)
+                  (twist 0 0 1)
+                  (grow B+ 2)
+                  (grow B+ 1 (twist 1)))))").unwrap();
+        let Some(TenscriptNode::Grow { forward, scale, branch: Some(branch), .. }) = plan.build_phase.growth else {
+            panic!("expected a grow with children");
+        };
+        assert_eq!(forward, vec![Chirality::Left, Chirality::Left, Chirality::Right]);
+        assert_eq!(scale, Some(0.95));
+        let TenscriptNode::Grow { face: FaceName::Bplus, forward, scale: None, branch: Some(last), .. } = *branch else {
+            panic!("expected children chained one after another");
+        };
+        assert_eq!(forward, vec![Chirality::Alternate; 2]);
+        assert!(matches!(*last, TenscriptNode::Grow { face: FaceName::Bplus, forward, branch: None, .. } if forward == [Chirality::Right]));
+        let forked = parse_err("(fabric (build (grow A+ (grow B+ 2 (grow C+)) (grow D+))))");
+        assert!(matches!(forked, MultipleBranches { .. }));
+        let mismatch = parse_err("(fabric (build (grow A+ 2 (twist 0 1 0))))");
+        assert!(matches!(mismatch, BadCall { context: "grow", .. }));
+        assert!(matches!(parse_err("(fabric (build (grow A+ 0)))"), TypeError { .. }));
+        assert!(matches!(parse_err("(fabric (build (grow A+ (twist))))"), BadCall { context: "grow", .. }));
+        let plan = parse("(fabric (build (grow A+ (mark A+ :end))))").unwrap();
+        assert!(matches!(plan.build_phase.growth, Some(TenscriptNode::Grow { forward, .. }) if forward == [Chirality::Alternate]));
+    }
+
+    #[test]
+    fn reports_bad_calls() {
+        let arity = parse_err("(fabric (def (limb count) (grow A+ count)) (build (limb)))");
+        assert!(matches!(arity, WrongArity { expected: 1, .. }));
+        let undefined = parse_err("(fabric (build (grow A+ 2 (limb))))");
+        assert!(matches!(undefined, Undefined { name, .. } if name == "limb"));
+        let recursion = parse_err("(fabric (def (limb) (grow A+ 1 (limb))) (build (limb)))");
+        assert!(matches!(recursion, RecursionLimit { name, .. } if name == "limb"));
+        assert!(matches!(parse_err("(fabric (features (iterations-per-frame -1)))"), Mismatch { rule: "features", .. }));
+        let extra = parse_err("(fabric (def (limb count) (grow A+ count)) (build (limb 1 2)))");
+        assert!(matches!(extra, WrongArity { expected: 1, .. }));
+        for name in ["grow", "branch", "gravity"] {
+            let source = format!("(fabric (def ({name}) (grow A+ 1)) (build ({name})))");
+            assert!(matches!(parse_err(&source), Reserved { name: reserved, .. } if reserved == name), "{}", source);
+        }
+    }
+
+    #[test]
+    fn composed_tree() {
+        let plan = parse("
+            (fabric
+              (seed :left)
+              (def (subtree scale-num)
+                (branch
+                  (grow B- 5)
+                  (grow C- 5)))
+              (branch
+                (grow A+ 6)
+                (grow b 4 (subtree 90%))
+                (grow C 4 (mark a :base))))").unwrap();
+        assert_eq!(plan.build_phase.seed, Some(SeedType::Left));
+        let Some(TenscriptNode::Branch { subtrees }) = plan.build_phase.growth else {
+            panic!("expected a branch");
+        };
+        assert!(matches!(&subtrees[1], TenscriptNode::Grow { face: FaceName::Bminus, branch: Some(branch), .. }
+            if matches!(branch.as_ref(), TenscriptNode::Branch { subtrees } if subtrees.len() == 2)));
+        assert!(matches!(&subtrees[2], TenscriptNode::Grow { face: FaceName::Cplus, marks, .. }
+            if marks[0].face == FaceName::Aminus));
+        let unused = parse_err("(fabric (def (subtree scale-num) (grow B- 5)) (grow b 4 (subtree)))");
+        assert!(matches!(unused, WrongArity { expected: 1, .. }));
+        let plan = parse("(fabric (build (grow A+ 2 (mark A+ :end)) (pull-together :end)))").unwrap();
+        assert!(matches!(&plan.shape_phase.operations[..], [ShapeOperation::PullTogether { mark_name, percent: None }] if mark_name == "end"));
+        let twice = parse_err("(fabric (seed :left) (build (seed :right)))");
+        assert!(matches!(twice, AlreadyDefined { property: "seed", .. }));
+    }
+}
@@ -3,8 +3,8 @@ use std::num::{ParseFloatError, ParseIntError};
 use std::str::FromStr;
 
 use crate::tenscript::error;
-use crate::tenscript::scanner::ErrorKind::{FloatParseFailed, IllegalChar, IntParseFailed};
-use crate::tenscript::scanner::Token::{Atom, EOF, Float, Ident, Integer, Paren, Percent, String as StringLit};
+use crate::tenscript::scanner::ErrorKind::{FloatParseFailed, IllegalChar, IllegalEscape, IntParseFailed, UnterminatedString};
+use crate::tenscript::scanner::Token::{Atom, Eof, Float, Ident, Integer, Paren, Percent, String as StringLit};
 
 #[derive(Debug, Clone)]
 pub enum Token {
@@ -15,19 +15,20 @@ pub enum Token {
     Integer(i64),
     Float(f64),
     Percent(f64),
-    EOF,
+    Eof,
 }
 
-#[derive(Debug, Clone, Default)]
+/// A position in the source, counting lines and columns from one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Location {
-    line: usize,
-    col: usize,
+    pub line: usize,
+    pub col: usize,
 }
 
-#[derive(Debug, Clone)]
-pub struct ScannedToken {
-    pub(crate) tok: Token,
-    loc: Location,
+impl Default for Location {
+    fn default() -> Self {
+        Self { line: 1, col: 1 }
+    }
 }
 
 impl Display for Location {
@@ -37,16 +38,41 @@ impl Display for Location {
     }
 }
 
+/// The stretch of source from `start` up to but not including `end`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start, end: other.end }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.start, f)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScannedToken {
+    pub(crate) tok: Token,
+    pub(crate) span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct ScanError {
-    kind: ErrorKind,
-    loc: Location,
+    pub(crate) kind: ErrorKind,
+    pub(crate) span: Span,
 }
 
 impl Display for ScanError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let ScanError { kind, loc } = self;
-        write!(f, "{kind:?} at {loc}")
+        let ScanError { kind, span } = self;
+        write!(f, "{kind} at {span}")
     }
 }
 
@@ -58,10 +84,24 @@ pub enum ErrorKind {
     IllegalChar { ch: char },
     IntParseFailed { err: ParseIntError },
     FloatParseFailed { err: ParseFloatError },
+    IllegalEscape { ch: char },
+    UnterminatedString,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IllegalChar { ch } => write!(f, "illegal character '{ch}'"),
+            IntParseFailed { err } => write!(f, "bad integer: {err}"),
+            FloatParseFailed { err } => write!(f, "bad float: {err}"),
+            IllegalEscape { ch } => write!(f, "illegal escape '\\{ch}'"),
+            UnterminatedString => write!(f, "unterminated string"),
+        }
+    }
 }
 
 pub fn scan(source: &str) -> Result<Vec<ScannedToken>, error::Error> {
-    Scanner::new(source).scan().map_err(error::Error::ScanError)
+    Scanner::new(source).scan().map_err(|err| error::ErrorKind::ScanError(err).into())
 }
 
 struct Scanner {
@@ -69,6 +109,7 @@ struct Scanner {
     tokens: Vec<ScannedToken>,
     index: usize,
     start: usize,
+    start_loc: Location,
     loc: Location,
 }
 
@@ -79,6 +120,7 @@ impl Scanner {
             tokens: Default::default(),
             start: 0,
             index: 0,
+            start_loc: Default::default(),
             loc: Default::default(),
         }
     }
@@ -86,10 +128,12 @@ impl Scanner {
     pub fn scan(mut self) -> Result<Vec<ScannedToken>, ScanError> {
         while !self.at_end() {
             self.start = self.index;
+            self.start_loc = self.loc;
             self.scan_token()
-                .map_err(|kind| ScanError { kind, loc: self.loc.clone() })?;
+                .map_err(|kind| ScanError { kind, span: self.span() })?;
         }
-        self.add(EOF);
+        self.start_loc = self.loc;
+        self.add(Eof);
         Ok(self.tokens)
     }
 
@@ -100,20 +144,20 @@ impl Scanner {
             'a'..='z' => self.ident(),
             'A'..='Z' => self.atom(false),
             ':' => self.atom(true),
-            '"' => self.string(),
-            '\n' => {
-                self.loc.line += 1;
-                self.loc.col = 0;
-                self.increment();
-            }
-            ' ' | '\t' => {
+            '"' => self.string()?,
+            ';' => self.comment(),
+            '\n' => self.newline(),
+            ' ' | '\t' | '\r' => {
                 self.increment()
             }
             ch @ ('(' | ')') => {
                 self.increment();
                 self.add(Paren(ch));
             }
-            ch => return Err(IllegalChar { ch }),
+            ch => {
+                self.increment();
+                return Err(IllegalChar { ch });
+            }
         }
         Ok(())
     }
@@ -123,8 +167,9 @@ impl Scanner {
         self.index >= self.chars.len()
     }
 
+    /// The character at the cursor, or '\0' past the end so that lookahead never panics.
     fn current(&self) -> char {
-        self.chars[self.index]
+        self.chars.get(self.index).copied().unwrap_or('\0')
     }
 
     fn increment(&mut self) {
@@ -132,11 +177,21 @@ impl Scanner {
         self.loc.col += 1;
     }
 
+    fn newline(&mut self) {
+        self.index += 1;
+        self.loc.line += 1;
+        self.loc.col = 1;
+    }
+
+
+    fn span(&self) -> Span {
+        Span { start: self.start_loc, end: self.loc }
+    }
 
     fn add(&mut self, tok: Token) {
         self.tokens.push(ScannedToken {
             tok,
-            loc: self.loc.clone(),
+            span: self.span(),
         })
     }
 
@@ -172,23 +227,22 @@ impl Scanner {
             while let ch @ '0'..='9' = self.current() {
                 num_string.push(ch);
                 self.increment();
-                let mut value = f64::from_str(&num_string)
-                    .map_err(|err| FloatParseFailed { err })?;
-                if negative {
-                    value = -value;
-                }
-                match self.current() {
-                    '%' => {
-                        self.increment();
-                        self.add(Percent(value));
-                    }
-                    _ => self.add(Float(value)),
-                };
             }
+            let mut value = f64::from_str(&num_string)
+                .map_err(|err| FloatParseFailed { err })?;
+            if negative {
+                value = -value;
+            }
+            match self.current() {
+                '%' => {
+                    self.increment();
+                    self.add(Percent(value));
+                }
+                _ => self.add(Float(value)),
+            };
         } else {
             let mut value = i64::from_str(&num_string)
                 .map_err(|err| IntParseFailed { err })?;
-            
             if negative {
                 value = -value;
             }
@@ -221,15 +275,85 @@ impl Scanner {
         let name = self.lexeme();
         self.add(Ident(name));
     }
-    fn string(&mut self) {
-        self.increment();
-        while self.current() != '"' {
-            self.increment();
+
+    fn string(&mut self) -> Result<(), ErrorKind> {
+        self.increment(); // skip opening '"'
+        let mut string = String::new();
+        loop {
+            if self.at_end() {
+                return Err(UnterminatedString);
+            }
+            match self.current() {
+                '"' => break,
+                '\\' => {
+                    self.increment();
+                    match self.current() {
+                        ch @ ('"' | '\\') => string.push(ch),
+                        _ if self.at_end() => return Err(UnterminatedString),
+                        ch => return Err(IllegalEscape { ch }),
+                    }
+                    self.increment();
+                }
+                '\n' => {
+                    string.push('\n');
+                    self.newline();
+                }
+                ch => {
+                    string.push(ch);
+                    self.increment();
+                }
+            }
         }
-        self.increment();
-        let mut string = self.lexeme();
-        string.remove(0);
-        string.remove(string.len() - 1);
+        self.increment(); // skip closing '"'
         self.add(StringLit(string));
+        Ok(())
+    }
+
+    fn comment(&mut self) {
+        while !self.at_end() && self.current() != '\n' {
+            self.increment();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        Scanner::new(source).scan().unwrap().into_iter().map(|scanned| scanned.tok).collect()
+    }
+
+    #[test]
+    fn scans_examples() {
+        let tokens = tokens(include_str!("examples.ss"));
+        assert!(matches!(tokens.last(), Some(Eof)));
+        assert!(tokens.iter().any(|token| matches!(token, Percent(value) if *value == 90.0)));
+    }
+
+    #[test]
+    fn comments_and_line_endings() {
+        let tokens = tokens("; leading comment\r\n(name \"x\") ; trailing\r\n");
+        assert!(matches!(tokens.as_slice(), [Paren('('), Ident(_), StringLit(s), Paren(')'), Eof] if s == "x"));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let tokens = tokens(r#""say \"hi\" \\ bye""#);
+        assert!(matches!(tokens.as_slice(), [StringLit(s), Eof] if s == r#"say "hi" \ bye"#));
+    }
+
+    #[test]
+    fn unterminated_string() {
+        for source in ["\"open", "\"open\\"] {
+            let err = Scanner::new(source).scan().unwrap_err();
+            assert!(matches!(err.kind, UnterminatedString));
+        }
+    }
+
+    #[test]
+    fn one_token_per_float() {
+        let tokens = tokens("1.25 -0.5 12.5%");
+        assert!(matches!(tokens.as_slice(), [Float(a), Float(b), Percent(c), Eof] if *a == 1.25 && *b == -0.5 && *c == 12.5));
     }
 }
\ No newline at end of file
@@ -1,20 +1,35 @@
 use std::fmt::{Debug, Display, Formatter};
 
-use crate::tenscript::error::Error;
+use crate::tenscript::error::{Error, ErrorKind as TenscriptErrorKind};
 use crate::tenscript::scanner;
-use crate::tenscript::scanner::{ScannedToken, Token};
-use crate::tenscript::scanner::Token::{Atom, EOF, Float, Ident, Integer, Paren, Percent};
+use crate::tenscript::scanner::{ScannedToken, Span, Token};
+use crate::tenscript::scanner::Token::{Atom, Eof, Float, Ident, Integer, Paren, Percent};
 use crate::tenscript::sexp::ErrorKind::{ConsumeFailed, MatchExhausted};
 
+/// Every expression remembers where it came from, so errors can point at it.
 #[derive(Clone)]
 pub enum Sexp {
-    List(Vec<Sexp>),
-    Ident(String),
-    Atom(String),
-    String(String),
-    Integer(i64),
-    Float(f64),
-    Percent(f64),
+    List(Vec<Sexp>, Span),
+    Ident(String, Span),
+    Atom(String, Span),
+    String(String, Span),
+    Integer(i64, Span),
+    Float(f64, Span),
+    Percent(f64, Span),
+}
+
+impl Sexp {
+    pub fn span(&self) -> Span {
+        match self {
+            Sexp::List(_, span) |
+            Sexp::Ident(_, span) |
+            Sexp::Atom(_, span) |
+            Sexp::String(_, span) |
+            Sexp::Integer(_, span) |
+            Sexp::Float(_, span) |
+            Sexp::Percent(_, span) => *span,
+        }
+    }
 }
 
 impl Debug for Sexp {
@@ -27,7 +42,7 @@ impl Debug for Sexp {
 impl Display for Sexp {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Sexp::List(terms) => {
+            Sexp::List(terms, _) => {
                 f.write_str("(")?;
                 for (i, term) in terms.iter().enumerate() {
                     Display::fmt(term, f)?;
@@ -38,20 +53,28 @@ impl Display for Sexp {
                 f.write_str(")")?;
                 Ok(())
             }
-            Sexp::Ident(name) => write!(f, "{name}"),
-            Sexp::Atom(value) => write!(f, ":{value}"),
-            Sexp::String(value) => write!(f, "\"{value}\""),
-            Sexp::Percent(value) => write!(f, "{value}%"),
-            Sexp::Float(value) => write!(f, "{value}"),
-            Sexp::Integer(value) => write!(f, "{value}"),
+            Sexp::Ident(name, _) => write!(f, "{name}"),
+            Sexp::Atom(value, _) if value.starts_with(|ch: char| ch.is_ascii_uppercase()) => write!(f, "{value}"),
+            Sexp::Atom(value, _) => write!(f, ":{value}"),
+            Sexp::String(value, _) => write!(f, "\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+            Sexp::Percent(value, _) => write!(f, "{value}%"),
+            Sexp::Float(value, _) => write!(f, "{value}"),
+            Sexp::Integer(value, _) => write!(f, "{value}"),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ParseError {
-    kind: ErrorKind,
-    token: ScannedToken,
+    pub(crate) kind: ErrorKind,
+    pub(crate) token: ScannedToken,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ParseError { kind, token } = self;
+        write!(f, "{kind} at {}", token.span)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -60,13 +83,28 @@ pub enum ErrorKind {
     ConsumeFailed { expected: &'static str },
 }
 
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchExhausted => write!(f, "unexpected token"),
+            ConsumeFailed { expected } => write!(f, "expected {expected}"),
+        }
+    }
+}
+
 pub fn parse(source: &str) -> Result<Sexp, Error> {
     let tokens = scanner::scan(source)?;
     parse_tokens(tokens)
 }
 
 pub fn parse_tokens(tokens: Vec<ScannedToken>) -> Result<Sexp, Error> {
-    Parser::new(tokens).parse().map_err(Error::SexpParseError)
+    Parser::new(tokens).parse().map_err(|err| TenscriptErrorKind::SexpParseError(err).into())
+}
+
+/// Every top-level expression in the source, such as all the fabrics in a file.
+pub fn parse_all(source: &str) -> Result<Vec<Sexp>, Error> {
+    let tokens = scanner::scan(source)?;
+    Parser::new(tokens).parse_all().map_err(|err| TenscriptErrorKind::SexpParseError(err).into())
 }
 
 struct Parser {
@@ -88,6 +126,16 @@ impl Parser {
             .map_err(|kind| ParseError { kind, token: self.current_scanned().clone() })
     }
 
+    pub fn parse_all(mut self) -> Result<Vec<Sexp>, ParseError> {
+        let mut sexps = Vec::new();
+        while !matches!(self.current(), Eof) {
+            let sexp = self.sexp()
+                .map_err(|kind| ParseError { kind, token: self.current_scanned().clone() })?;
+            sexps.push(sexp);
+        }
+        Ok(sexps)
+    }
+
     fn current_scanned(&self) -> &ScannedToken {
         &self.tokens[self.index]
     }
@@ -102,37 +150,44 @@ impl Parser {
     }
 
     fn sexp(&mut self) -> Result<Sexp, ErrorKind> {
-        let token = self.current().clone();
-        self.increment();
-        match token {
-            Paren('(') =>
-                self.list(),
+        let ScannedToken { tok, span } = self.current_scanned().clone();
+        match tok {
+            Paren('(') => {
+                self.increment();
+                self.list(span)
+            }
             Ident(name) =>
-                Ok(Sexp::Ident(name)),
+                self.leaf(Sexp::Ident(name, span)),
             Float(value) =>
-                Ok(Sexp::Float(value)),
+                self.leaf(Sexp::Float(value, span)),
             Integer(value) =>
-                Ok(Sexp::Integer(value)),
+                self.leaf(Sexp::Integer(value, span)),
             Percent(value) =>
-                Ok(Sexp::Percent(value)),
+                self.leaf(Sexp::Percent(value, span)),
             Atom(value) =>
-                Ok(Sexp::Atom(value)),
+                self.leaf(Sexp::Atom(value, span)),
             Token::String(value) =>
-                Ok(Sexp::String(value)),
+                self.leaf(Sexp::String(value, span)),
             _ => Err(MatchExhausted),
         }
     }
 
-    fn list(&mut self) -> Result<Sexp, ErrorKind> {
+    fn leaf(&mut self, sexp: Sexp) -> Result<Sexp, ErrorKind> {
+        self.increment();
+        Ok(sexp)
+    }
+
+    fn list(&mut self, open: Span) -> Result<Sexp, ErrorKind> {
         let mut terms = Vec::new();
-        while !matches!(self.current(), Paren(')') | EOF) {
+        while !matches!(self.current(), Paren(')') | Eof) {
             let term = self.sexp()?;
             terms.push(term);
         }
         let Paren(')') = self.current() else {
             return Err(ConsumeFailed { expected: "right paren" });
         };
+        let close = self.current_scanned().span;
         self.increment();
-        Ok(Sexp::List(terms))
+        Ok(Sexp::List(terms, open.to(close)))
     }
 }
\ No newline at end of file
@@ -3,16 +3,24 @@
  * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
  */
 
+use crate::constants::Real;
 use crate::fabric::{Fabric, DEFAULT_STRAIN_LIMITS};
 use crate::world::World;
 use nalgebra::*;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+/// The renderer always gets `f32`, whatever the simulation runs in.
+#[allow(clippy::unnecessary_cast)] // only unnecessary while the simulation is in f32
+pub(crate) fn render_float(value: Real) -> f32 {
+    value as f32
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct View {
-    pub(crate) midpoint: Point3<f32>,
-    pub(crate) mass: f32,
-    pub(crate) radius: f32,
+    pub(crate) midpoint: Point3<Real>,
+    pub(crate) mass: Real,
+    pub(crate) radius: Real,
     pub(crate) joint_locations: Vec<f32>,
     pub(crate) joint_velocities: Vec<f32>,
     pub(crate) line_locations: Vec<f32>,
@@ -29,7 +37,7 @@ pub struct View {
     pub(crate) linear_densities: Vec<f32>,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl View {
     pub fn on_fabric(fabric: &Fabric) -> View {
         let joint_count = fabric.get_joint_count() as usize;
@@ -37,8 +45,8 @@ impl View {
         let face_count = fabric.get_face_count() as usize;
         View {
             midpoint: Point3::origin(),
-            mass: 0_f32,
-            radius: 2_f32,
+            mass: 0.0,
+            radius: 2.0,
             joint_locations: Vec::with_capacity(joint_count * 3),
             joint_velocities: Vec::with_capacity(joint_count * 3),
             line_locations: Vec::with_capacity(interval_count * 2 * 3),
@@ -49,7 +57,7 @@ impl View {
             unit_vectors: Vec::with_capacity(interval_count * 3),
             ideal_lengths: Vec::with_capacity(interval_count),
             strains: Vec::with_capacity(interval_count),
-            strain_limits: DEFAULT_STRAIN_LIMITS.to_vec(),
+            strain_limits: DEFAULT_STRAIN_LIMITS.iter().copied().map(render_float).collect(),
             strain_nuances: Vec::with_capacity(interval_count),
             stiffnesses: Vec::with_capacity(interval_count),
             linear_densities: Vec::with_capacity(interval_count),
@@ -62,9 +70,9 @@ impl View {
             joint.project(self);
         }
         self.midpoint /= self.mass;
-        let mut radius_squared = 0_f32;
+        let mut radius_squared = 0.0;
         for joint in fabric.joints.iter() {
-            let from_midpoint = &joint.location - &self.midpoint;
+            let from_midpoint = joint.location - self.midpoint;
             let squared = from_midpoint.magnitude_squared();
             if radius_squared < squared {
                 radius_squared = squared
@@ -73,11 +81,11 @@ impl View {
         self.radius = radius_squared.sqrt();
         let pretensing_nuance = world.pretensing_nuance(fabric);
         for interval in fabric.intervals.iter() {
-            let current_length = interval.calculate_current_length(&fabric.joints) + 0.01_f32;
+            let current_length = interval.calculate_current_length(&fabric.joints) + 0.01;
             let ideal_length = interval.ideal_length_now(world, fabric.stage, pretensing_nuance);
             let slack_pull = !interval.push && ideal_length > current_length;
             let extend = if slack_pull {
-                0_f32
+                0.0
             } else {
                 interval.strain * ideal_length * world.visual_strain
             };
@@ -86,10 +94,10 @@ impl View {
             } else {
                 extend
             };
-            interval.project_line_locations(self, &fabric.joints, bounded_extend / -2_f32);
+            interval.project_line_locations(self, &fabric.joints, bounded_extend / -2.0);
             interval.project_line_features(self, ideal_length)
         }
-        self.strain_limits = fabric.strain_limits.to_vec();
+        self.strain_limits = fabric.strain_limits.iter().copied().map(render_float).collect();
         for interval in fabric.intervals.iter() {
             interval.project_line_color_nuance(self)
         }
@@ -98,21 +106,21 @@ impl View {
         }
     }
 
-    pub fn midpoint_x(&self) -> f32 {
+    pub fn midpoint_x(&self) -> Real {
         self.midpoint.x
     }
 
-    pub fn midpoint_y(&self) -> f32 {
+    pub fn midpoint_y(&self) -> Real {
         self.midpoint.y
     }
 
-    pub fn midpoint_z(&self) -> f32 {
+    pub fn midpoint_z(&self) -> Real {
         self.midpoint.z
     }
 
-    pub fn radius(&self) -> f32 {
-        if self.radius < 2_f32 {
-            2_f32
+    pub fn radius(&self) -> Real {
+        if self.radius < 2.0 {
+            2.0
         } else {
             self.radius
         }
@@ -176,7 +184,7 @@ impl View {
 
     fn clear(&mut self) {
         self.midpoint.coords.fill(0.0);
-        self.mass = 0_f32;
+        self.mass = 0.0;
         self.joint_locations.clear();
         self.joint_velocities.clear();
         self.line_locations.clear();
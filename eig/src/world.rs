@@ -3,35 +3,175 @@
  * Licensed under GNU GENERAL PUBLIC LICENSE Version 3.
  */
 
+use std::fmt::{Display, Formatter};
+
 use crate::constants::*;
+use crate::diagnostics::{SettledCriterion, DEFAULT_SETTLED_CRITERION};
 use crate::fabric::Fabric;
+use crate::tenscript::{FabricPlan, Features};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureError {
+    PercentOutOfRange { feature: WorldFeature, percent: f64, min: f64, max: f64 },
+    IterationsOutOfRange { iterations: u32, min: u32, max: u32 },
+}
+
+impl Display for FeatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FeatureError::PercentOutOfRange { feature, percent, min, max } =>
+                write!(f, "{feature:?} of {percent}% is outside {min}%..{max}%"),
+            FeatureError::IterationsOutOfRange { iterations, min, max } =>
+                write!(f, "{iterations} iterations per frame is outside {min}..{max}"),
+        }
+    }
+}
+
+impl std::error::Error for FeatureError {}
+
+/// The time step of one tick, in which all the other features are expressed.
+pub const DEFAULT_TIME_STEP: Real = 1.0;
+
+/// The radius of a push seen as a bar, for collisions, in model units.
+pub const DEFAULT_BAR_RADIUS: Real = 0.04;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct World {
     pub(crate) surface_character: SurfaceCharacter,
     pub(crate) push_and_pull: bool,
-    pub(crate) gravity: f32,
-    pub(crate) drag: f32,
-    pub(crate) pretenst_factor: f32,
-    pub(crate) stiffness_factor: f32,
-    pub(crate) iterations_per_frame: f32,
-    pub(crate) interval_countdown: f32,
-    pub(crate) pretensing_countdown: f32,
-    pub(crate) shaping_pretenst_factor: f32,
-    pub(crate) shaping_drag: f32,
-    pub(crate) shaping_stiffness_factor: f32,
-    pub(crate) visual_strain: f32,
-    pub(crate) push_over_pull: f32,
-    pub(crate) antigravity: f32,
+    pub(crate) integrator: Integrator,
+    pub(crate) failure_mode: FailureMode,
+    pub(crate) collision_mode: CollisionMode,
+    pub(crate) bar_radius: Real,
+    pub(crate) time_step: Real,
+    pub(crate) settled_criterion: SettledCriterion,
+    pub(crate) gravity: Real,
+    pub(crate) drag: Real,
+    pub(crate) pretenst_factor: Real,
+    pub(crate) stiffness_factor: Real,
+    pub(crate) iterations_per_frame: Real,
+    pub(crate) interval_countdown: Real,
+    pub(crate) pretensing_countdown: Real,
+    pub(crate) shaping_pretenst_factor: Real,
+    pub(crate) shaping_drag: Real,
+    pub(crate) shaping_stiffness_factor: Real,
+    pub(crate) visual_strain: Real,
+    pub(crate) push_over_pull: Real,
+    pub(crate) antigravity: Real,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
 }
 
-#[wasm_bindgen]
+impl World {
+    /// A world with the plan's surface and features applied to the defaults.
+    pub fn from_plan(plan: &FabricPlan) -> Result<World, FeatureError> {
+        let mut world = World::new();
+        if let Some(surface_character) = plan.surface {
+            world.surface_character = surface_character;
+        }
+        world.apply_features(&plan.features)?;
+        Ok(world)
+    }
+
+    /// When `Fabric::is_settled` says the fabric is at rest.
+    pub fn set_settled_criterion(&mut self, settled_criterion: SettledCriterion) {
+        self.settled_criterion = settled_criterion;
+    }
+
+    /// Apply features given as percents of their defaults, except iterations per frame which is a count.
+    /// Nothing is changed unless every feature is within range.
+    pub fn apply_features(&mut self, features: &Features) -> Result<(), FeatureError> {
+        check_features(features)?;
+        if let Some(iterations) = features.iterations_per_frame {
+            self.set_float_value(WorldFeature::IterationsPerFrame, iterations as Real);
+        }
+        for (feature, percent) in feature_percents(features) {
+            self.set_float_percent(feature, percent as Real);
+        }
+        Ok(())
+    }
+}
+
+pub fn check_features(features: &Features) -> Result<(), FeatureError> {
+    if let Some(iterations) = features.iterations_per_frame {
+        let (min, max) = iterations_per_frame_range();
+        if !(min..=max).contains(&iterations) {
+            return Err(FeatureError::IterationsOutOfRange { iterations, min, max });
+        }
+    }
+    for (feature, percent) in feature_percents(features) {
+        let (min, max) = percent_range(feature);
+        if !(min..=max).contains(&percent) {
+            return Err(FeatureError::PercentOutOfRange { feature, percent, min, max });
+        }
+    }
+    Ok(())
+}
+
+fn feature_percents(features: &Features) -> impl Iterator<Item=(WorldFeature, f64)> {
+    IntoIterator::into_iter([
+        (WorldFeature::VisualStrain, features.visual_strain),
+        (WorldFeature::Gravity, features.gravity),
+        (WorldFeature::PretenstFactor, features.pretenst_factor),
+        (WorldFeature::StiffnessFactor, features.stiffness_factor),
+        (WorldFeature::PushOverPull, features.push_over_pull),
+        (WorldFeature::Drag, features.drag),
+        (WorldFeature::ShapingPretenstFactor, features.shaping_pretenst_factor),
+        (WorldFeature::ShapingDrag, features.shaping_drag),
+        (WorldFeature::ShapingStiffnessFactor, features.shaping_stiffness_factor),
+        (WorldFeature::Antigravity, features.antigravity),
+        (WorldFeature::IntervalCountdown, features.interval_countdown),
+        (WorldFeature::PretensingCountdown, features.pretensing_countdown),
+    ])
+        .filter_map(|(feature, percent)| Some((feature, percent?)))
+}
+
+/// The percents of the default which the client's feature sliders allow, widened where the
+/// designs it ships with go further.
+fn percent_range(feature: WorldFeature) -> (f64, f64) {
+    match feature {
+        WorldFeature::Gravity => (0.0, 2000.0),
+        WorldFeature::Antigravity => (5.0, 500.0),
+        WorldFeature::ShapingDrag => (0.0, 500.0),
+        WorldFeature::ShapingStiffnessFactor => (10.0, 5000.0),
+        WorldFeature::Drag => (0.0, 1000.0),
+        WorldFeature::ShapingPretenstFactor => (0.0, 1000.0),
+        WorldFeature::PretenstFactor => (0.0, 500.0),
+        WorldFeature::StiffnessFactor => (1.0, 1000.0),
+        WorldFeature::IterationsPerFrame => (2.0, 2000.0),
+        WorldFeature::IntervalCountdown => (10.0, 1000.0),
+        WorldFeature::PretensingCountdown => (50.0, 200.0),
+        WorldFeature::VisualStrain => (0.0, 300.0),
+        WorldFeature::PushOverPull => (10.0, 2000.0),
+    }
+}
+
+/// Plans give iterations per frame as a count, so its percent range becomes a range of counts.
+fn iterations_per_frame_range() -> (u32, u32) {
+    let (min, max) = percent_range(WorldFeature::IterationsPerFrame);
+    let count = |percent: f64| percent * default_world_feature(WorldFeature::IterationsPerFrame) as f64 / 100.0;
+    (count(min).ceil() as u32, count(max).floor() as u32)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl World {
     pub fn new() -> World {
         World {
             surface_character: SurfaceCharacter::Bouncy,
             push_and_pull: false,
+            integrator: Integrator::SemiImplicitEuler,
+            failure_mode: FailureMode::Off,
+            collision_mode: CollisionMode::Off,
+            bar_radius: DEFAULT_BAR_RADIUS,
+            time_step: DEFAULT_TIME_STEP,
+            settled_criterion: DEFAULT_SETTLED_CRITERION,
             gravity: default_world_feature(WorldFeature::Gravity),
             drag: default_world_feature(WorldFeature::Drag),
             pretenst_factor: default_world_feature(WorldFeature::PretenstFactor),
@@ -56,7 +196,30 @@ impl World {
         self.push_and_pull = push_and_pull;
     }
 
-    pub fn get_float_value(&self, feature: WorldFeature) -> f32 {
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    /// Whether pushes buckle and pulls rupture, which only intervals with a material can.
+    pub fn set_failure_mode(&mut self, failure_mode: FailureMode) {
+        self.failure_mode = failure_mode;
+    }
+
+    /// Whether pushes that clash as bars are reported, or also pushed apart.
+    pub fn set_collision_mode(&mut self, collision_mode: CollisionMode) {
+        self.collision_mode = collision_mode;
+    }
+
+    pub fn set_bar_radius(&mut self, bar_radius: Real) {
+        self.bar_radius = bar_radius;
+    }
+
+    /// The dt of each tick. Stiffer fabrics need a smaller step or the implicit integrator.
+    pub fn set_time_step(&mut self, time_step: Real) {
+        self.time_step = time_step;
+    }
+
+    pub fn get_float_value(&self, feature: WorldFeature) -> Real {
         match feature {
             WorldFeature::Gravity => self.gravity,
             WorldFeature::Drag => self.drag,
@@ -74,8 +237,8 @@ impl World {
         }
     }
 
-    pub fn set_float_value(&mut self, feature: WorldFeature, value: f32) -> f32 {
-        let value_pointer: &mut f32 = match feature {
+    pub fn set_float_value(&mut self, feature: WorldFeature, value: Real) -> Real {
+        let value_pointer: &mut Real = match feature {
             WorldFeature::Gravity => &mut self.gravity,
             WorldFeature::Drag => &mut self.drag,
             WorldFeature::PretenstFactor => &mut self.pretenst_factor,
@@ -94,16 +257,69 @@ impl World {
         value
     }
 
-    pub fn set_float_percent(&mut self, feature: WorldFeature, percent: f32) -> f32 {
-        let value = percent * default_world_feature(feature) / 100_f32;
+    pub fn set_float_percent(&mut self, feature: WorldFeature, percent: Real) -> Real {
+        let value = percent * default_world_feature(feature) / 100.0;
         self.set_float_value(feature, value)
     }
 
-    pub fn pretensing_nuance(&self, fabric: &Fabric) -> f32 {
+    pub fn pretensing_nuance(&self, fabric: &Fabric) -> Real {
         if fabric.stage <= Stage::Slack {
-            0_f32
+            0.0
         } else {
             (self.pretensing_countdown - fabric.pretensing_countdown) / self.pretensing_countdown
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenscript::parse;
+
+    #[test]
+    fn applies_plan_features() {
+        let plan = parse("(fabric (surface :frozen) (features (iterations-per-frame 100) (gravity 50%)) (build))").unwrap();
+        let world = World::from_plan(&plan).unwrap();
+        assert_eq!(world.surface_character, SurfaceCharacter::Frozen);
+        assert_eq!(world.iterations_per_frame, 100.0);
+        let half_gravity = default_world_feature(WorldFeature::Gravity) / 2.0;
+        assert!((world.gravity - half_gravity).abs() < half_gravity * 1e-6);
+        let plan = parse("(fabric (features (push-over-pull 3000%)) (build))").unwrap();
+        let err = World::from_plan(&plan).err();
+        assert!(matches!(err, Some(FeatureError::PercentOutOfRange { feature: WorldFeature::PushOverPull, .. })));
+        let plan = parse("(fabric (features (iterations-per-frame 1001)) (build))").unwrap();
+        let err = World::from_plan(&plan).err();
+        assert!(matches!(err, Some(FeatureError::IterationsOutOfRange { min: 1, max: 1000, .. })));
+    }
+
+    #[test]
+    fn accepts_example_features() {
+        for plan in crate::tenscript::parse_all(include_str!("tenscript/examples.ss")).unwrap() {
+            check_features(&plan.features).unwrap();
+            if let Some(pretense_phase) = &plan.pretense_phase {
+                check_features(&pretense_phase.features).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_client_designs() {
+        // feature percents from the client's bootstrap designs and view presets
+        let designs = [
+            (WorldFeature::PushOverPull, [2000.0, 1000.0, 400.0]),
+            (WorldFeature::IterationsPerFrame, [300.0, 1000.0, 100.0]),
+            (WorldFeature::Gravity, [50.0, 1500.0, 0.0]),
+            (WorldFeature::StiffnessFactor, [500.0, 800.0, 100.0]),
+            (WorldFeature::ShapingStiffnessFactor, [600.0, 1000.0, 5000.0]),
+            (WorldFeature::ShapingDrag, [300.0, 10.0, 100.0]),
+            (WorldFeature::Drag, [0.0, 100.0, 100.0]),
+            (WorldFeature::VisualStrain, [0.0, 100.0, 100.0]),
+        ];
+        for (feature, percents) in designs {
+            let (min, max) = percent_range(feature);
+            for percent in percents {
+                assert!((min..=max).contains(&percent), "{:?} {}%", feature, percent);
+            }
+        }
+    }
+}